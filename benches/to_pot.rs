@@ -0,0 +1,37 @@
+//! Measure the time taken by [`GettextWriter::to_pot`] on a synthetic catalog the size of the whole game.
+//! Run with ``cargo bench``.
+
+use std::time::Instant;
+use translatepmd::{Entry, GettextWriter};
+
+/// Approximately the number of messages in PSMD
+const MESSAGE_COUNT: u32 = 60_000;
+/// One message out of this number reuse the text of an earlier message
+const DUPLICATE_EVERY: u32 = 4;
+
+fn main() {
+    let mut catalog = GettextWriter::new(vec!["Yes".to_string()]);
+    for message in 0..MESSAGE_COUNT {
+        let text_id = if message % DUPLICATE_EVERY == 0 {
+            message / DUPLICATE_EVERY
+        } else {
+            message
+        };
+        catalog.entries.push(Entry::new(
+            format!("[CS:A]Message[CR] number {}, with a bit of text.", text_id),
+            message,
+            0,
+            format!("script_{}.bin", message / 200),
+        ));
+    }
+
+    let start = Instant::now();
+    let pot = catalog.to_pot();
+    let elapsed = start.elapsed();
+    println!(
+        "to_pot with {} messages: {:?} ({} bytes of output)",
+        MESSAGE_COUNT,
+        elapsed,
+        pot.len()
+    );
+}
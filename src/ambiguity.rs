@@ -0,0 +1,342 @@
+use crate::{DiscriminatorRule, Entry, GettextWriter, RuleMatcher};
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+/// The criteria of the strings that may be ambiguous: shared by several messages, but possibly needing a
+/// different translation in each place. A shared string matching any of them is ambiguous.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AmbiguityCriteria {
+    /// Strings of at most this number of characters
+    pub max_length: Option<usize>,
+    /// Strings used in at least this number of different source files
+    pub min_source_files: Option<usize>,
+    /// Strings made of a single word
+    #[serde(default)]
+    pub single_word: bool,
+}
+
+impl AmbiguityCriteria {
+    /// Return true if no criterion is set, so no string can be ambiguous
+    pub fn is_empty(&self) -> bool {
+        self.max_length.is_none() && self.min_source_files.is_none() && !self.single_word
+    }
+}
+
+/// Why a string is considered ambiguous
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmbiguityReason {
+    /// The string has this number of characters
+    Short(usize),
+    /// The string is used in this number of source files
+    ManySourceFiles(usize),
+    SingleWord,
+}
+
+impl fmt::Display for AmbiguityReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Short(length) => write!(f, "short ({} characters)", length),
+            Self::ManySourceFiles(count) => write!(f, "used in {} files", count),
+            Self::SingleWord => write!(f, "single word"),
+        }
+    }
+}
+
+/// A string shared by several messages that may need a different translation in each place
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmbiguousString {
+    pub text: String,
+    /// The number of messages with this text
+    pub message_count: usize,
+    /// The source files of those messages, sorted
+    pub source_files: Vec<String>,
+    pub reasons: Vec<AmbiguityReason>,
+}
+
+impl AmbiguousString {
+    /// The discriminator rule translating this string separately at each location
+    pub fn to_rule(&self) -> DiscriminatorRule {
+        // the rule file is line based, so the line breaks are written as \n and \r in the regex
+        let pattern = format!(
+            "^{}$",
+            regex::escape(&self.text)
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+        );
+        DiscriminatorRule {
+            text: format!("regex {}", pattern),
+            matcher: RuleMatcher::Regex(Regex::new(&pattern).unwrap()),
+            exception: false,
+            line: None,
+        }
+    }
+}
+
+fn is_single_word(text: &str) -> bool {
+    let text = text.trim();
+    !text.contains(char::is_whitespace) && text.chars().any(char::is_alphabetic)
+}
+
+impl GettextWriter {
+    /// Find the strings shared by several messages that match the ambiguity criteria, in the order of their first
+    /// message. Strings already translated separately (with a context or a discriminator rule), or kept together by
+    /// an exception rule, are ignored.
+    pub fn find_ambiguous(&self, criteria: &AmbiguityCriteria) -> Vec<AmbiguousString> {
+        if criteria.is_empty() {
+            return Vec::new();
+        };
+        let mut texts: Vec<(&str, Vec<&Entry>)> = Vec::new();
+        let mut text_index: HashMap<&str, usize> = HashMap::new();
+        for entry in &self.entries {
+            if entry.text.is_empty()
+                || entry.context.is_some()
+                || self.discriminator.deciding_rule(entry).is_some()
+            {
+                continue;
+            };
+            let index = *text_index.entry(&entry.text).or_insert_with(|| {
+                texts.push((&entry.text, Vec::new()));
+                texts.len() - 1
+            });
+            texts[index].1.push(entry);
+        }
+
+        let mut result = Vec::new();
+        for (text, entries) in texts {
+            if entries.len() < 2 {
+                continue;
+            };
+            let source_files: BTreeSet<&str> = entries
+                .iter()
+                .map(|entry| entry.source_file.as_str())
+                .collect();
+            let mut reasons = Vec::new();
+            let length = text.chars().count();
+            if criteria.max_length.is_some_and(|max| length <= max) {
+                reasons.push(AmbiguityReason::Short(length));
+            };
+            if criteria
+                .min_source_files
+                .is_some_and(|min| source_files.len() >= min)
+            {
+                reasons.push(AmbiguityReason::ManySourceFiles(source_files.len()));
+            };
+            if criteria.single_word && is_single_word(text) {
+                reasons.push(AmbiguityReason::SingleWord);
+            };
+            if !reasons.is_empty() {
+                result.push(AmbiguousString {
+                    text: text.to_string(),
+                    message_count: entries.len(),
+                    source_files: source_files.into_iter().map(str::to_string).collect(),
+                    reasons,
+                });
+            };
+        }
+        result
+    }
+}
+
+/// Write the review list of the ambiguous strings, as a discriminator rule file. Translators remove the rules of
+/// the strings that should keep a single translation, and give the file to the discriminator rules.
+pub fn write_ambiguity_review(ambiguous: &[AmbiguousString]) -> String {
+    let mut result = String::from(
+        "# Strings shared by several messages, that may need a different translation in each place.\n\
+         # Each rule translate a string separately at each location. Remove the rules (or precede them with \"!\")\n\
+         # of the strings that should keep a single translation, then use this file as a discriminator file.\n",
+    );
+    for string in ambiguous {
+        let reasons: Vec<String> = string
+            .reasons
+            .iter()
+            .map(|reason| reason.to_string())
+            .collect();
+        result.push_str(&format!(
+            "\n# {} messages in {}: {}\n{}\n",
+            string.message_count,
+            string.source_files.join(", "),
+            reasons.join(", "),
+            string.to_rule().text
+        ));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Discriminator;
+
+    fn catalog(messages: &[(&str, u32, &str)]) -> GettextWriter {
+        let mut catalog = GettextWriter::new(Vec::new());
+        for (source_file, hash, text) in messages {
+            catalog.entries.push(Entry::new(
+                text.to_string(),
+                *hash,
+                0,
+                source_file.to_string(),
+            ));
+        }
+        catalog
+    }
+
+    fn entry(text: &str) -> Entry {
+        Entry::new(text.to_string(), 1, 0, "a.bin".to_string())
+    }
+
+    #[test]
+    fn find_ambiguous_strings() {
+        let mut catalog = catalog(&[
+            ("b.bin", 1, "Go to the next floor"),
+            ("a.bin", 1, "Yes"),
+            ("a.bin", 2, "Go to the next floor"),
+            ("b.bin", 2, "Yes"),
+            ("c.bin", 1, "Go to the next floor"),
+            ("a.bin", 3, "Alone"),
+            ("a.bin", 4, ""),
+            ("b.bin", 3, ""),
+            ("a.bin", 5, "No"),
+            ("a.bin", 6, "No"),
+        ]);
+        catalog.entries[9].context = Some("a.bin 6".to_string());
+
+        assert!(catalog
+            .find_ambiguous(&AmbiguityCriteria::default())
+            .is_empty());
+
+        let ambiguous = catalog.find_ambiguous(&AmbiguityCriteria {
+            max_length: Some(3),
+            min_source_files: Some(3),
+            single_word: true,
+        });
+        assert_eq!(
+            ambiguous,
+            vec![
+                AmbiguousString {
+                    text: "Go to the next floor".to_string(),
+                    message_count: 3,
+                    source_files: vec![
+                        "a.bin".to_string(),
+                        "b.bin".to_string(),
+                        "c.bin".to_string()
+                    ],
+                    reasons: vec![AmbiguityReason::ManySourceFiles(3)],
+                },
+                AmbiguousString {
+                    text: "Yes".to_string(),
+                    message_count: 2,
+                    source_files: vec!["a.bin".to_string(), "b.bin".to_string()],
+                    reasons: vec![AmbiguityReason::Short(3), AmbiguityReason::SingleWord],
+                },
+            ]
+        );
+
+        let ambiguous = catalog.find_ambiguous(&AmbiguityCriteria {
+            single_word: true,
+            ..Default::default()
+        });
+        assert_eq!(ambiguous.len(), 1);
+        assert_eq!(ambiguous[0].text, "Yes");
+
+        // the strings already decided by a rule are ignored
+        catalog.set_discriminator(Discriminator::from_substrings(vec!["yes".to_string()]));
+        assert!(catalog
+            .find_ambiguous(&AmbiguityCriteria {
+                single_word: true,
+                ..Default::default()
+            })
+            .is_empty());
+    }
+
+    #[test]
+    fn single_words() {
+        assert!(is_single_word("Yes"));
+        assert!(is_single_word(" Yes!\n"));
+        assert!(!is_single_word("Yes sir"));
+        assert!(!is_single_word("Line\nbreak"));
+        assert!(!is_single_word("..."));
+    }
+
+    #[test]
+    fn rules_are_parsed_back() {
+        for text in [
+            "Yes",
+            "!Yes",
+            "! Yes",
+            "Line\nbreak\r\nand more",
+            "a.b*c+d?(e)[f]{g}^h$|i\\j",
+            " spaces ",
+            "# not a comment",
+        ] {
+            let string = AmbiguousString {
+                text: text.to_string(),
+                message_count: 2,
+                source_files: vec!["a.bin".to_string()],
+                reasons: vec![AmbiguityReason::SingleWord],
+            };
+            let rule = string.to_rule();
+            assert!(rule.is_match(&entry(text)), "{:?}", text);
+            let parsed = DiscriminatorRule::parse(&rule.text, 1).unwrap();
+            assert!(!parsed.exception, "{:?}", text);
+            assert!(parsed.is_match(&entry(text)), "{:?}", text);
+            for other in [
+                format!("{}x", text),
+                format!("x{}", text),
+                text.trim().to_string(),
+            ] {
+                if other != text {
+                    assert!(
+                        !parsed.is_match(&entry(&other)),
+                        "{:?} matched {:?}",
+                        text,
+                        other
+                    );
+                };
+            }
+
+            // the same through a review file
+            let mut discriminator = Discriminator::default();
+            discriminator
+                .add_rules_file(&write_ambiguity_review(&[string]))
+                .unwrap();
+            let rule = discriminator.splitting_rule(&entry(text)).unwrap();
+            assert_eq!(rule.line, Some(6));
+        }
+    }
+
+    #[test]
+    fn fallback_rules_lose_to_exceptions() {
+        let mut catalog = catalog(&[
+            ("script.bin", 1, "Yes"),
+            ("menu.bin", 0x12C, "Yes"),
+            ("script.bin", 2, "Yes"),
+        ]);
+        let mut discriminator = Discriminator::default();
+        discriminator
+            .add_rules_file("! hash menu.bin:0x0000012C\n")
+            .unwrap();
+        catalog.set_discriminator(discriminator.clone());
+        let ambiguous = catalog.find_ambiguous(&AmbiguityCriteria {
+            single_word: true,
+            ..Default::default()
+        });
+        assert_eq!(ambiguous.len(), 1);
+        assert_eq!(ambiguous[0].message_count, 2);
+
+        discriminator.add_fallback_rule(ambiguous[0].to_rule());
+        let menu_rule = discriminator.deciding_rule(&catalog.entries[1]).unwrap();
+        assert!(menu_rule.exception);
+        assert!(discriminator.splitting_rule(&catalog.entries[1]).is_none());
+        for entry in [&catalog.entries[0], &catalog.entries[2]] {
+            assert_eq!(
+                discriminator.splitting_rule(entry).unwrap().text,
+                "regex ^Yes$"
+            );
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use crate::{write_po_location, GettextWriter, MessageError, MessagePart, PlaceholderTable};
+use pmd_code_table::CodeTable;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fmt,
+    path::PathBuf,
+};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Common typographic characters, with the replacement suggested when they can't be encoded
+const REPLACEMENTS: &[(char, &str)] = &[
+    ('\u{2018}', "'"),
+    ('\u{2019}', "'"),
+    ('\u{201A}', "'"),
+    ('\u{201B}', "'"),
+    ('\u{201C}', "\""),
+    ('\u{201D}', "\""),
+    ('\u{201E}', "\""),
+    ('\u{00AB}', "\""),
+    ('\u{00BB}', "\""),
+    ('\u{2026}', "..."),
+    ('\u{2013}', "-"),
+    ('\u{2014}', "-"),
+    ('\u{2212}', "-"),
+    ('\u{00A0}', " "),
+    ('\u{202F}', " "),
+    ('\u{2009}', " "),
+    ('\u{0153}', "oe"),
+    ('\u{0152}', "OE"),
+    ('\u{00E6}', "ae"),
+    ('\u{00C6}', "AE"),
+    ('\u{00DF}', "ss"),
+];
+
+/// Why a character of a translation can't be written in the message files
+#[derive(Debug, Clone, PartialEq)]
+pub enum CharacterProblem {
+    /// The character doesn't fit in a single 16 bit code (like most emojis)
+    OutsideBmp,
+    /// The code of the character is read back by the game as this placeholder of the code table
+    Reserved(String),
+    /// The character isn't in the charset of the game, so it can't be displayed
+    Unsupported,
+}
+
+impl fmt::Display for CharacterProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutsideBmp => write!(f, "doesn't fit in a 16 bit code"),
+            Self::Reserved(name) => write!(f, "has the code of the placeholder [{}]", name),
+            Self::Unsupported => write!(f, "isn't in the charset of the game"),
+        }
+    }
+}
+
+/// The characters that can be written in the message files of the game
+#[derive(Debug, Default, Clone)]
+pub struct Charset {
+    /// The names of the placeholders of the code table, by code. The game read every character with one of
+    /// those codes, or whose code with its low byte cleared is one of those, as a placeholder.
+    reserved: HashMap<u16, String>,
+    /// The characters the game can display, if known
+    supported: Option<HashSet<char>>,
+}
+
+impl Charset {
+    pub fn new(code_table: &CodeTable) -> Self {
+        Self {
+            reserved: code_table
+                .entries()
+                .iter()
+                .map(|entry| (entry.value, entry.string.clone()))
+                .collect(),
+            supported: None,
+        }
+    }
+
+    /// Add characters the game can display. Once some are added, the other characters are refused.
+    pub fn add_supported(&mut self, characters: impl IntoIterator<Item = char>) {
+        self.supported
+            .get_or_insert_with(HashSet::new)
+            .extend(characters);
+    }
+
+    /// Add the characters of a charset file, which contain every character the game can display. Line breaks
+    /// are ignored.
+    pub fn add_charset_file(&mut self, content: &str) {
+        self.add_supported(content.chars().filter(|ch| *ch != '\n' && *ch != '\r'));
+    }
+
+    /// Return why a character can't be written, if it can't. Line breaks and spaces are always supported.
+    pub fn problem(&self, character: char) -> Option<CharacterProblem> {
+        let code = match u16::try_from(character as u32) {
+            Ok(code) => code,
+            Err(_) => return Some(CharacterProblem::OutsideBmp),
+        };
+        if let Some(name) = self
+            .reserved
+            .get(&code)
+            .or_else(|| self.reserved.get(&(code & 0xFF00)))
+        {
+            return Some(CharacterProblem::Reserved(name.clone()));
+        };
+        match &self.supported {
+            Some(supported)
+                if !matches!(character, '\n' | '\r' | ' ') && !supported.contains(&character) =>
+            {
+                Some(CharacterProblem::Unsupported)
+            }
+            _ => None,
+        }
+    }
+
+    /// Suggest a replacement for a character that can't be written: its typographic equivalent (like ``'`` for
+    /// ``’``), or the letter without its accent (like ``e`` for ``ê``). The replacement is only suggested if it
+    /// can be written.
+    pub fn suggest(&self, character: char) -> Option<String> {
+        let typographic = REPLACEMENTS
+            .iter()
+            .find(|(original, _)| *original == character)
+            .map(|(_, replacement)| replacement.to_string());
+        let decomposed: Vec<char> = character.to_string().nfd().collect();
+        // only letters with an accent are replaced by their base letter, not other decomposed characters
+        let base_letter = if decomposed.iter().any(|ch| is_combining_mark(*ch)) {
+            Some(
+                decomposed
+                    .into_iter()
+                    .filter(|ch| !is_combining_mark(*ch))
+                    .collect::<String>(),
+            )
+        } else {
+            None
+        };
+        typographic
+            .into_iter()
+            .chain(base_letter)
+            .find(|replacement| {
+                !replacement.is_empty() && replacement.chars().all(|ch| self.problem(ch).is_none())
+            })
+    }
+}
+
+/// A problem that prevent writing a translation in the message files
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodingProblem {
+    /// The translation can't be parsed, like with an unescaped ``[`` or an unknown placeholder
+    InvalidText(MessageError),
+    /// A character can't be written. Contain its position in the translation, and a suggested replacement.
+    Character(usize, char, CharacterProblem, Option<String>),
+}
+
+impl fmt::Display for EncodingProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidText(err) => write!(f, "invalid translation, {}", err),
+            Self::Character(position, character, problem, suggestion) => {
+                write!(
+                    f,
+                    "character {}: {:?} (U+{:04X}) {}",
+                    position, character, *character as u32, problem
+                )?;
+                match suggestion {
+                    Some(suggestion) => write!(f, ", replace it with {:?}", suggestion),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// A problem in the translation of a message, with the messages and the po entry it come from
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodingIssue {
+    /// The ``(source file, hash)`` of the messages with this translation
+    pub messages: Vec<(String, u32)>,
+    pub po_file: Option<PathBuf>,
+    /// The line of the ``msgstr`` in the po file
+    pub po_line: Option<usize>,
+    pub problem: EncodingProblem,
+}
+
+impl fmt::Display for EncodingIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_po_location(f, &self.po_file, self.po_line, &self.messages)?;
+        write!(f, " {}", self.problem)
+    }
+}
+
+/// Return the problems of a translation. The placeholders are refused in the same cases as when the translation
+/// is encoded with the code table, so a translation without problem can be encoded. Every invalid placeholder is
+/// reported, but an error in the syntax of the text stop the check.
+fn encoding_problems(
+    translation: &str,
+    charset: &Charset,
+    table: &PlaceholderTable,
+) -> Vec<EncodingProblem> {
+    let (message, errors) = match table.parse_lenient(translation) {
+        Ok(result) => result,
+        Err(err) => return vec![EncodingProblem::InvalidText(err)],
+    };
+    let characters: Vec<char> = translation.chars().collect();
+    let mut invalid_placeholders = errors.into_iter().peekable();
+    // the invalid placeholders are left out of the message, so they are reported when their position is reached
+    let mut skip_invalid_placeholders =
+        |position: &mut usize, problems: &mut Vec<EncodingProblem>| {
+            while let Some(err) = invalid_placeholders.next_if(|err| err.position() == *position) {
+                *position += characters[*position..]
+                    .iter()
+                    .position(|character| *character == ']')
+                    .map_or(0, |end| end + 1);
+                problems.push(EncodingProblem::InvalidText(err));
+            }
+        };
+    let mut problems = Vec::new();
+    // the position in the written text, where placeholders and escapes take several characters
+    let mut position = 0;
+    for part in &message {
+        match part {
+            MessagePart::Text(text) => {
+                for character in text.chars() {
+                    skip_invalid_placeholders(&mut position, &mut problems);
+                    if let Some(problem) = charset.problem(character) {
+                        problems.push(EncodingProblem::Character(
+                            position,
+                            character,
+                            problem,
+                            charset.suggest(character),
+                        ));
+                    };
+                    position += if matches!(character, '[' | '\\') {
+                        2
+                    } else {
+                        1
+                    };
+                }
+            }
+            MessagePart::Placeholder(placeholder) => {
+                skip_invalid_placeholders(&mut position, &mut problems);
+                position += placeholder.to_string().chars().count()
+            }
+        }
+    }
+    skip_invalid_placeholders(&mut position, &mut problems);
+    problems
+}
+
+impl GettextWriter {
+    /// Check that every translation can be written in the message files, returning all the problems found.
+    /// Messages sharing a po entry are checked together.
+    pub fn check_encoding(
+        &self,
+        charset: &Charset,
+        table: &PlaceholderTable,
+    ) -> Vec<EncodingIssue> {
+        let mut issues = Vec::new();
+        for checked in self.po_translations() {
+            for problem in encoding_problems(checked.translation, charset, table) {
+                issues.push(EncodingIssue {
+                    messages: checked.messages.clone(),
+                    po_file: checked.po_file.clone(),
+                    po_line: checked.po_line,
+                    problem,
+                });
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn charset(supported: Option<&str>) -> Charset {
+        let mut charset = Charset {
+            reserved: [(0xE000, "CENTER"), (0xE105, "K"), (0x8100, "COLOR:")]
+                .iter()
+                .map(|(code, name)| (*code, name.to_string()))
+                .collect(),
+            supported: None,
+        };
+        if let Some(supported) = supported {
+            charset.add_charset_file(supported);
+        };
+        charset
+    }
+
+    fn table() -> PlaceholderTable {
+        PlaceholderTable::from_entries(
+            [("CENTER", 0), ("K", 0), ("COLOR:", 0), ("VAR:", 1)]
+                .iter()
+                .map(|(name, length)| (name.to_string(), *length)),
+        )
+    }
+
+    #[test]
+    fn reserved_codes() {
+        let charset = charset(None);
+        assert_eq!(
+            charset.problem('\u{E000}'),
+            Some(CharacterProblem::Reserved("CENTER".to_string()))
+        );
+        assert_eq!(
+            charset.problem('\u{E105}'),
+            Some(CharacterProblem::Reserved("K".to_string()))
+        );
+        // the game read the low byte of those codes as the argument of the placeholder
+        assert_eq!(
+            charset.problem('\u{81FF}'),
+            Some(CharacterProblem::Reserved("COLOR:".to_string()))
+        );
+        assert_eq!(
+            charset.problem('\u{E001}'),
+            Some(CharacterProblem::Reserved("CENTER".to_string()))
+        );
+        assert_eq!(charset.problem('\u{E100}'), None);
+        assert_eq!(charset.problem('\u{8200}'), None);
+        assert_eq!(charset.problem('😀'), Some(CharacterProblem::OutsideBmp));
+        assert_eq!(charset.problem('é'), None);
+    }
+
+    #[test]
+    fn supported_characters() {
+        let charset = charset(Some("abcdeoE\r\n'"));
+        assert_eq!(charset.problem('a'), None);
+        assert_eq!(charset.problem('E'), None);
+        assert_eq!(charset.problem(' '), None);
+        assert_eq!(charset.problem('\n'), None);
+        assert_eq!(charset.problem('z'), Some(CharacterProblem::Unsupported));
+        assert_eq!(charset.problem('é'), Some(CharacterProblem::Unsupported));
+        // a reserved code is reported even if it is in the charset
+        let mut charset = charset;
+        charset.add_supported(['\u{E000}']);
+        assert_eq!(
+            charset.problem('\u{E000}'),
+            Some(CharacterProblem::Reserved("CENTER".to_string()))
+        );
+    }
+
+    #[test]
+    fn suggest_replacements() {
+        let charset = charset(Some("abcdeoE'\"."));
+        assert_eq!(charset.suggest('ê').as_deref(), Some("e"));
+        assert_eq!(charset.suggest('É').as_deref(), Some("E"));
+        assert_eq!(charset.suggest('’').as_deref(), Some("'"));
+        assert_eq!(charset.suggest('«').as_deref(), Some("\""));
+        assert_eq!(charset.suggest('…').as_deref(), Some("..."));
+        assert_eq!(charset.suggest('œ').as_deref(), Some("oe"));
+        // the replacement should be supported too
+        assert_eq!(charset.suggest('Œ'), None);
+        assert_eq!(charset.suggest('ü'), None);
+        assert_eq!(charset.suggest('z'), None);
+        // only the accents are removed, not the other decompositions
+        assert_eq!(charset.suggest('ﬁ'), None);
+    }
+
+    #[test]
+    fn encoding_problems_of_translations() {
+        let charset = charset(Some("abcdeoE'[\\"));
+        let table = table();
+        assert!(encoding_problems("a [CENTER]b\\[c\\\\[COLOR:5]", &charset, &table).is_empty());
+        assert_eq!(
+            encoding_problems("\\[a[CENTER]ê\\\\z", &charset, &table),
+            vec![
+                EncodingProblem::Character(
+                    11,
+                    'ê',
+                    CharacterProblem::Unsupported,
+                    Some("e".to_string())
+                ),
+                EncodingProblem::Character(14, 'z', CharacterProblem::Unsupported, None),
+            ]
+        );
+        assert_eq!(
+            encoding_problems("[BAD]ê[VAR:x][CENTER]z[COLOR:256]", &charset, &table),
+            vec![
+                EncodingProblem::InvalidText(MessageError::UnknownPlaceholder(
+                    0,
+                    "BAD".to_string()
+                )),
+                EncodingProblem::Character(
+                    5,
+                    'ê',
+                    CharacterProblem::Unsupported,
+                    Some("e".to_string())
+                ),
+                EncodingProblem::InvalidText(MessageError::InvalidArgument(
+                    6,
+                    "VAR".to_string(),
+                    "x".to_string()
+                )),
+                EncodingProblem::Character(21, 'z', CharacterProblem::Unsupported, None),
+                EncodingProblem::InvalidText(MessageError::ArgumentTooBig(
+                    22,
+                    "COLOR".to_string(),
+                    "256".to_string(),
+                    255
+                )),
+            ]
+        );
+        assert_eq!(
+            encoding_problems("\u{E000}", &charset, &table),
+            vec![EncodingProblem::Character(
+                0,
+                '\u{E000}',
+                CharacterProblem::Reserved("CENTER".to_string()),
+                None
+            )]
+        );
+    }
+
+    #[test]
+    fn encoding_problems_match_the_encoder_errors() {
+        let charset = charset(None);
+        let table = table();
+        for (translation, error) in [
+            ("a\\b", MessageError::UselessEscape(1, 'b')),
+            ("a\\", MessageError::UnfinishedEscape(1)),
+            ("[CENTER", MessageError::UnclosedPlaceholder(0)),
+            ("[]", MessageError::EmptyPlaceholder(0)),
+            (
+                "[UNKNOWN]",
+                MessageError::UnknownPlaceholder(0, "UNKNOWN".to_string()),
+            ),
+            (
+                "[CENTER:1]",
+                MessageError::UnknownPlaceholder(0, "CENTER:1".to_string()),
+            ),
+            (
+                "[VAR:x]",
+                MessageError::InvalidArgument(0, "VAR".to_string(), "x".to_string()),
+            ),
+            (
+                "[VAR:4294967296]",
+                MessageError::ArgumentTooBig(
+                    0,
+                    "VAR".to_string(),
+                    "4294967296".to_string(),
+                    u32::MAX,
+                ),
+            ),
+            (
+                "[COLOR:256]",
+                MessageError::ArgumentTooBig(0, "COLOR".to_string(), "256".to_string(), 255),
+            ),
+            (
+                "[VAR:1:2]",
+                MessageError::TooManyParts(0, "VAR:1:2".to_string()),
+            ),
+        ] {
+            assert_eq!(
+                encoding_problems(translation, &charset, &table),
+                vec![EncodingProblem::InvalidText(error)],
+                "{:?}",
+                translation
+            );
+        }
+    }
+}
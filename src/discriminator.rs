@@ -0,0 +1,159 @@
+use crate::{grouping::match_file_pattern, parse_reference, Entry};
+use regex::Regex;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DiscriminatorError {
+    #[error("line {0}: the rule {1:?} should start with contains, regex, file or hash")]
+    UnknownRule(usize, String),
+    #[error("line {0}: the rule {1:?} doesn't have a value")]
+    MissingValue(usize, String),
+    #[error("line {0}: invalid regex in the rule {1:?}")]
+    InvalidRegex(usize, String, #[source] regex::Error),
+    #[error(
+        "line {0}: the message of the rule {1:?} should be of the form <source file>:0x<hash>"
+    )]
+    MalformedHash(usize, String),
+}
+
+/// What a [`DiscriminatorRule`] match
+#[derive(Debug, Clone)]
+pub enum RuleMatcher {
+    /// Texts containing this lowercase string, ignoring the case
+    Contains(String),
+    /// Texts matching this regex
+    Regex(Regex),
+    /// Every text of the source files matching this pattern (like ``script_m01*``)
+    File(String),
+    /// The message with this source file and hash
+    Hash(String, u32),
+}
+
+/// A rule deciding whether an entry is translated separately at each of its locations
+#[derive(Debug, Clone)]
+pub struct DiscriminatorRule {
+    pub matcher: RuleMatcher,
+    /// An exception: the matched entries are not translated separately, even if an earlier rule say so
+    pub exception: bool,
+    /// The text of the rule, as written in the rule file
+    pub text: String,
+    /// The line of the rule in the rule file, if it come from one
+    pub line: Option<usize>,
+}
+
+impl DiscriminatorRule {
+    /// Parse a rule like ``regex ^Yes$``, ``contains yes``, ``file script_m01*``, ``hash menu.bin:0x0000012C``,
+    /// optionally preceded by ``!`` for an exception
+    pub fn parse(rule: &str, line: usize) -> Result<Self, DiscriminatorError> {
+        let (exception, rule_without_exception) = match rule.strip_prefix('!') {
+            Some(rule) => (true, rule.trim_start()),
+            None => (false, rule),
+        };
+        let (kind, value) = rule_without_exception
+            .split_once(char::is_whitespace)
+            .map(|(kind, value)| (kind, value.trim()))
+            .unwrap_or((rule_without_exception, ""));
+        if value.is_empty() {
+            return Err(DiscriminatorError::MissingValue(line, rule.to_string()));
+        };
+        let matcher = match kind {
+            "contains" => RuleMatcher::Contains(value.to_lowercase()),
+            "regex" => RuleMatcher::Regex(
+                Regex::new(value)
+                    .map_err(|err| DiscriminatorError::InvalidRegex(line, rule.to_string(), err))?,
+            ),
+            "file" => RuleMatcher::File(value.to_string()),
+            "hash" => {
+                let (source_file, hash) = parse_reference(value, line)
+                    .map_err(|_| DiscriminatorError::MalformedHash(line, rule.to_string()))?;
+                RuleMatcher::Hash(source_file, hash)
+            }
+            _ => return Err(DiscriminatorError::UnknownRule(line, rule.to_string())),
+        };
+        Ok(Self {
+            matcher,
+            exception,
+            text: rule.to_string(),
+            line: Some(line),
+        })
+    }
+
+    /// Return true if this rule match the entry
+    pub fn is_match(&self, entry: &Entry) -> bool {
+        match &self.matcher {
+            RuleMatcher::Contains(text) => entry.text.to_lowercase().contains(text),
+            RuleMatcher::Regex(regex) => regex.is_match(&entry.text),
+            RuleMatcher::File(pattern) => match_file_pattern(pattern, &entry.source_file),
+            RuleMatcher::Hash(source_file, hash) => {
+                entry.hash == *hash && &entry.source_file == source_file
+            }
+        }
+    }
+}
+
+impl fmt::Display for DiscriminatorRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.text),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
+
+/// The rules deciding which entries are translated separately at each of their locations, rather than
+/// sharing a single translation. Like in a ``.gitignore`` file, the last rule matching an entry decide.
+#[derive(Debug, Default, Clone)]
+pub struct Discriminator {
+    rules: Vec<DiscriminatorRule>,
+}
+
+impl Discriminator {
+    /// Create a discriminator splitting the texts containing one of those strings, ignoring the case
+    pub fn from_substrings(substrings: Vec<String>) -> Self {
+        let mut result = Self::default();
+        for substring in substrings {
+            result.add_rule(DiscriminatorRule {
+                text: format!("contains {}", substring),
+                matcher: RuleMatcher::Contains(substring.to_lowercase()),
+                exception: false,
+                line: None,
+            });
+        }
+        result
+    }
+
+    /// Add a rule, that take priority over the previous ones
+    pub fn add_rule(&mut self, rule: DiscriminatorRule) {
+        self.rules.push(rule);
+    }
+
+    /// Add a rule, that is overridden by all the current ones
+    pub fn add_fallback_rule(&mut self, rule: DiscriminatorRule) {
+        self.rules.insert(0, rule);
+    }
+
+    /// Add the rules of a rule file, with one rule per line (see [`DiscriminatorRule::parse`]).
+    /// Empty lines and lines starting with ``#`` are ignored.
+    pub fn add_rules_file(&mut self, content: &str) -> Result<(), DiscriminatorError> {
+        for (line_nb, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            };
+            self.add_rule(DiscriminatorRule::parse(line, line_nb + 1)?);
+        }
+        Ok(())
+    }
+
+    /// Return the last rule matching this entry, that decide if it is translated separately. It is an exception
+    /// if the entry was explicitly kept with the other messages with the same text.
+    pub fn deciding_rule(&self, entry: &Entry) -> Option<&DiscriminatorRule> {
+        self.rules.iter().rev().find(|rule| rule.is_match(entry))
+    }
+
+    /// Return the rule that split this entry, if it should be translated separately
+    pub fn splitting_rule(&self, entry: &Entry) -> Option<&DiscriminatorRule> {
+        self.deciding_rule(entry).filter(|rule| !rule.exception)
+    }
+}
@@ -0,0 +1,96 @@
+use std::ops::Range;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GroupingError {
+    #[error("line {0}: the rule {1:?} should be of the form <source file, or prefix followed by *> = <group>")]
+    MalformedRule(usize, String),
+}
+
+/// Return true if ``source_file`` match the pattern. A pattern ending with ``*`` match every source file starting
+/// with the text before it, other patterns match a single source file.
+pub(crate) fn match_file_pattern(pattern: &str, source_file: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => source_file.starts_with(prefix),
+        None => source_file == pattern,
+    }
+}
+
+/// How the messages are split in the po files of a folder.
+///
+/// By default, there is one po file per source file, named after the text before its first ``.``.
+/// Rules can put several source files in the same group, and big groups can be split in numbered parts.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PoGrouping {
+    /// The ``(pattern, group)`` rules, checked in order, with patterns as in [`match_file_pattern`]
+    rules: Vec<(String, String)>,
+    /// The maximal number of entries of a po file. Bigger groups are split in parts of at most this size.
+    pub max_entries: Option<usize>,
+    /// Remove the po files of the folder that aren't part of the grouping when it is written, like the ones of an
+    /// earlier grouping. They are otherwise kept, and read along with the new files.
+    pub prune: bool,
+}
+
+impl PoGrouping {
+    /// Add a rule putting the source files matching ``pattern`` in ``group``. Earlier rules have priority.
+    pub fn add_rule(&mut self, pattern: String, group: String) {
+        self.rules.push((pattern, group));
+    }
+
+    /// Parse a rule of the form ``<pattern> = <group>``
+    pub fn parse_rule(rule: &str) -> Option<(String, String)> {
+        let (pattern, group) = rule.split_once('=')?;
+        let (pattern, group) = (pattern.trim(), group.trim());
+        if pattern.is_empty() || group.is_empty() {
+            return None;
+        };
+        Some((pattern.to_string(), group.to_string()))
+    }
+
+    /// Add the rules of a grouping file, with one ``<pattern> = <group>`` rule per line, like ``script_m01* = chapter_1``
+    /// or ``menu.bin = ui``. Empty lines and lines starting with ``#`` are ignored.
+    pub fn add_rules_file(&mut self, content: &str) -> Result<(), GroupingError> {
+        for (line_nb, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            };
+            let (pattern, group) = Self::parse_rule(line)
+                .ok_or_else(|| GroupingError::MalformedRule(line_nb + 1, line.to_string()))?;
+            self.add_rule(pattern, group);
+        }
+        Ok(())
+    }
+
+    /// Return the group of the messages of ``source_file``
+    pub fn group_of(&self, source_file: &str) -> String {
+        for (pattern, group) in &self.rules {
+            if match_file_pattern(pattern, source_file) {
+                return group.clone();
+            };
+        }
+        source_file.split('.').next().unwrap_or("").to_string()
+    }
+
+    /// Split the ``entry_count`` entries of a group into parts, returning the name of the po file and the range
+    /// of entries of each part
+    pub(crate) fn parts(&self, group: &str, entry_count: usize) -> Vec<(String, Range<usize>)> {
+        match self.max_entries {
+            Some(max_entries) if max_entries > 0 && entry_count > max_entries => {
+                // the part numbers have the same width, so the files are sorted in order
+                let width = ((entry_count - 1) / max_entries + 1).to_string().len();
+                (0..entry_count)
+                    .step_by(max_entries)
+                    .enumerate()
+                    .map(|(part, start)| {
+                        (
+                            format!("{}-{:0width$}.po", group, part + 1, width = width),
+                            start..(start + max_entries).min(entry_count),
+                        )
+                    })
+                    .collect()
+            }
+            _ => vec![(format!("{}.po", group), 0..entry_count)],
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::PoEntry;
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The name of the standard fields of the header, in the order they are written, and the order of
+/// [`PoHeader::fields`]
+const FIELD_NAMES: [&str; 12] = [
+    "Project-Id-Version",
+    "Report-Msgid-Bugs-To",
+    "POT-Creation-Date",
+    "PO-Revision-Date",
+    "Last-Translator",
+    "Language-Team",
+    "Language",
+    "MIME-Version",
+    "Content-Type",
+    "Content-Transfer-Encoding",
+    "Plural-Forms",
+    "X-Generator",
+];
+
+/// The header of a po file: the metadata stored in the msgstr of the entry with an empty msgid
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PoHeader {
+    pub project_id_version: Option<String>,
+    pub report_msgid_bugs_to: Option<String>,
+    pub pot_creation_date: Option<String>,
+    pub po_revision_date: Option<String>,
+    pub last_translator: Option<String>,
+    pub language_team: Option<String>,
+    pub language: Option<String>,
+    pub mime_version: Option<String>,
+    pub content_type: Option<String>,
+    pub content_transfer_encoding: Option<String>,
+    pub plural_forms: Option<String>,
+    pub x_generator: Option<String>,
+    /// The other fields, in the order they were found
+    pub other: Vec<(String, String)>,
+    /// The comments of the header entry (like a copyright notice)
+    pub translator_comments: Vec<String>,
+    /// The flags of the header entry (a new template is usually marked fuzzy)
+    pub flags: Vec<String>,
+}
+
+impl PoHeader {
+    /// Create the header of a newly generated template
+    pub fn new_template(project_id_version: String, language: String) -> Self {
+        Self {
+            project_id_version: Some(project_id_version),
+            pot_creation_date: Some(format_po_date(creation_time())),
+            language: Some(language),
+            mime_version: Some("1.0".to_string()),
+            content_type: Some("text/plain; charset=UTF-8".to_string()),
+            content_transfer_encoding: Some("8bit".to_string()),
+            x_generator: Some(format!("pmdtranslate {}", env!("CARGO_PKG_VERSION"))),
+            ..Default::default()
+        }
+    }
+
+    fn fields(&self) -> [&Option<String>; FIELD_NAMES.len()] {
+        [
+            &self.project_id_version,
+            &self.report_msgid_bugs_to,
+            &self.pot_creation_date,
+            &self.po_revision_date,
+            &self.last_translator,
+            &self.language_team,
+            &self.language,
+            &self.mime_version,
+            &self.content_type,
+            &self.content_transfer_encoding,
+            &self.plural_forms,
+            &self.x_generator,
+        ]
+    }
+
+    fn fields_mut(&mut self) -> [&mut Option<String>; FIELD_NAMES.len()] {
+        [
+            &mut self.project_id_version,
+            &mut self.report_msgid_bugs_to,
+            &mut self.pot_creation_date,
+            &mut self.po_revision_date,
+            &mut self.last_translator,
+            &mut self.language_team,
+            &mut self.language,
+            &mut self.mime_version,
+            &mut self.content_type,
+            &mut self.content_transfer_encoding,
+            &mut self.plural_forms,
+            &mut self.x_generator,
+        ]
+    }
+
+    /// Parse the content of the header (the msgstr of the header entry), made of ``Name: value`` lines
+    pub fn parse(content: &str) -> Self {
+        let mut result = Self::default();
+        for line in content.lines() {
+            let (name, value) = match line.split_once(':') {
+                Some((name, value)) => (name.trim(), value.trim().to_string()),
+                None => continue,
+            };
+            match FIELD_NAMES
+                .iter()
+                .position(|field_name| field_name.eq_ignore_ascii_case(name))
+            {
+                Some(index) => *result.fields_mut()[index] = Some(value),
+                None => result.other.push((name.to_string(), value)),
+            }
+        }
+        result
+    }
+
+    /// Return the header contained in this entry, if it is the header entry
+    pub fn from_po_entry(entry: &PoEntry) -> Option<Self> {
+        if !entry.is_header() || entry.obsolete {
+            return None;
+        };
+        let mut result = Self::parse(entry.msgstr.first().map(String::as_str).unwrap_or(""));
+        result.translator_comments = entry.translator_comments.clone();
+        result.flags = entry.flags.clone();
+        Some(result)
+    }
+
+    /// Write the content of the header, as stored in the msgstr of the header entry
+    pub fn to_content(&self) -> String {
+        let mut result = String::new();
+        for (name, value) in FIELD_NAMES.iter().zip(self.fields().iter()) {
+            if let Some(value) = value {
+                result.push_str(&format!("{}: {}\n", name, value));
+            }
+        }
+        for (name, value) in &self.other {
+            result.push_str(&format!("{}: {}\n", name, value));
+        }
+        result
+    }
+
+    /// Return the header entry, to be put at the start of the po file
+    pub fn to_po_entry(&self) -> PoEntry {
+        PoEntry {
+            translator_comments: self.translator_comments.clone(),
+            flags: self.flags.clone(),
+            msgstr: vec![self.to_content()],
+            ..Default::default()
+        }
+    }
+}
+
+/// The time a template is created at. It can be fixed with the ``SOURCE_DATE_EPOCH`` environment variable, to
+/// get reproducible files.
+fn creation_time() -> u64 {
+    creation_time_from(env::var("SOURCE_DATE_EPOCH").ok().as_deref())
+}
+
+/// The time a template is created at, for the given value of ``SOURCE_DATE_EPOCH``. The current time is used
+/// if it isn't set, or isn't a valid timestamp.
+fn creation_time_from(source_date_epoch: Option<&str>) -> u64 {
+    source_date_epoch
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default()
+        })
+}
+
+/// Format a unix timestamp as a po date (like ``2022-03-20 14:05+0000``), in UTC
+fn format_po_date(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds_in_day = timestamp % 86400;
+    // convert the number of days since 1970-01-01 into a date (from Howard Hinnant's civil_from_days)
+    let shifted_days = days + 719468;
+    let era = shifted_days / 146097;
+    let day_of_era = shifted_days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}+0000",
+        year,
+        month,
+        day,
+        seconds_in_day / 3600,
+        seconds_in_day % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_dates() {
+        assert_eq!(format_po_date(0), "1970-01-01 00:00+0000");
+        assert_eq!(format_po_date(951782400), "2000-02-29 00:00+0000");
+        assert_eq!(format_po_date(1647785100), "2022-03-20 14:05+0000");
+        assert_eq!(format_po_date(4107542399), "2100-02-28 23:59+0000");
+    }
+
+    #[test]
+    fn creation_time_follow_source_date_epoch() {
+        assert_eq!(creation_time_from(Some("1647785100")), 1647785100);
+
+        let now = || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        for source_date_epoch in [None, Some(""), Some("yesterday"), Some("-1")] {
+            let before = now();
+            let time = creation_time_from(source_date_epoch);
+            assert!(
+                before <= time && time <= now(),
+                "{:?} gave {}",
+                source_date_epoch,
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_kept() {
+        let content = concat!(
+            "Project-Id-Version: PSMD US\n",
+            "X-Poedit-Basepath: ..\n",
+            "language: fr\n",
+            "Plural-Forms: nplurals=2; plural=(n > 1);\n",
+            "X-Custom: a: b\n",
+        );
+        let header = PoHeader::parse(content);
+        assert_eq!(header.language.as_deref(), Some("fr"));
+        assert_eq!(
+            header.other,
+            vec![
+                ("X-Poedit-Basepath".to_string(), "..".to_string()),
+                ("X-Custom".to_string(), "a: b".to_string()),
+            ]
+        );
+        // the standard fields are written first, with their usual case
+        assert_eq!(
+            header.to_content(),
+            concat!(
+                "Project-Id-Version: PSMD US\n",
+                "Language: fr\n",
+                "Plural-Forms: nplurals=2; plural=(n > 1);\n",
+                "X-Poedit-Basepath: ..\n",
+                "X-Custom: a: b\n",
+            )
+        );
+        assert_eq!(PoHeader::parse(&header.to_content()), header);
+
+        let mut entry = header.to_po_entry();
+        entry.translator_comments = vec!["Copyright".to_string()];
+        entry.flags = vec!["fuzzy".to_string()];
+        let from_entry = PoHeader::from_po_entry(&entry).unwrap();
+        assert_eq!(from_entry.other, header.other);
+        assert_eq!(from_entry.to_po_entry(), entry);
+    }
+}
@@ -0,0 +1,497 @@
+use crate::{write_po_location, GettextWriter, Message, MessagePart, PlaceholderTable};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::PathBuf,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LayoutError {
+    #[error("line {0}: {1:?} should be of the form <width> <characters>, or default <width>")]
+    MalformedWidth(usize, String),
+    #[error("line {0}: {1:?} isn't a valid U+<hex code> character")]
+    InvalidCharacter(usize, String),
+}
+
+/// The width of each glyph of the font, in pixels
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GlyphWidths {
+    widths: HashMap<char, u32>,
+    /// The width of the characters that aren't in the table. Default to the widest glyph of the table.
+    pub default_width: Option<u32>,
+}
+
+impl GlyphWidths {
+    /// A table where every character has the same width. With a width of 1, the widths are counted in characters.
+    pub fn monospace(width: u32) -> Self {
+        Self {
+            widths: HashMap::new(),
+            default_width: Some(width),
+        }
+    }
+
+    pub fn set(&mut self, character: char, width: u32) {
+        self.widths.insert(character, width);
+    }
+
+    /// Return the width of a character, or the default width if it isn't in the table
+    pub fn width(&self, character: char) -> u32 {
+        self.widths.get(&character).copied().unwrap_or_else(|| {
+            self.default_width
+                .unwrap_or_else(|| self.widths.values().copied().max().unwrap_or(0))
+        })
+    }
+
+    pub fn text_width(&self, text: &str) -> u32 {
+        text.chars().map(|character| self.width(character)).sum()
+    }
+
+    /// Parse a glyph width file. Each line is of the form ``<width> <characters>``, like ``6 abcdeg``, where
+    /// the characters are separated in words, and a word of the form ``U+<hex code>`` is a single character (like
+    /// ``U+0020`` for the space). ``default <width>`` set the width of the characters that aren't in the file.
+    /// Empty lines and lines starting with ``#`` are ignored.
+    pub fn parse(content: &str) -> Result<Self, LayoutError> {
+        let mut result = Self::default();
+        for (line_nb, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            };
+            let malformed = || LayoutError::MalformedWidth(line_nb + 1, line.to_string());
+            let (width, characters) = line.split_once(char::is_whitespace).ok_or_else(malformed)?;
+            if width == "default" {
+                result.default_width = Some(characters.trim().parse().map_err(|_| malformed())?);
+                continue;
+            };
+            let width: u32 = width.parse().map_err(|_| malformed())?;
+            for word in characters.split_whitespace() {
+                match word.strip_prefix("U+") {
+                    Some(code) if !code.is_empty() => {
+                        let character = u32::from_str_radix(code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| {
+                                LayoutError::InvalidCharacter(line_nb + 1, word.to_string())
+                            })?;
+                        result.set(character, width);
+                    }
+                    _ => {
+                        for character in word.chars() {
+                            result.set(character, width);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// How the messages are laid out in the dialogue box, and its limits
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub glyph_widths: GlyphWidths,
+    /// The maximal width of a line, in pixels
+    pub max_width: Option<u32>,
+    /// The maximal number of lines of a box
+    pub max_lines: Option<usize>,
+    /// true if the lines too wide are wrapped at spaces, false if every line must fit as written
+    pub wrap: bool,
+    /// The names of the placeholders that start a new box, like ``K`` for ``[K]``
+    pub page_breaks: HashSet<String>,
+    /// The longest text of the placeholders replaced by a variable text, like ``PARTNERNAME``, by name.
+    /// The other placeholders take no space.
+    pub placeholder_texts: HashMap<String, String>,
+}
+
+impl Layout {
+    pub fn new(glyph_widths: GlyphWidths) -> Self {
+        Self {
+            glyph_widths,
+            max_width: None,
+            max_lines: None,
+            wrap: true,
+            page_breaks: HashSet::new(),
+            placeholder_texts: HashMap::new(),
+        }
+    }
+
+    /// Parse a rule of the form ``<placeholder name>=<longest text>``, like ``PARTNERNAME=Mmmmmmmmmm``, and add it
+    /// to [`Layout::placeholder_texts`]. Return false if it isn't of this form.
+    pub fn add_placeholder_text(&mut self, rule: &str) -> bool {
+        match rule.split_once('=') {
+            Some((name, text)) if !name.trim().is_empty() => {
+                self.placeholder_texts
+                    .insert(name.trim().to_string(), text.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Lay out a message, returning the width of each line of each box
+    pub fn boxes(&self, message: &Message) -> Vec<Vec<u32>> {
+        let mut boxes = Vec::new();
+        let mut writer = LineWriter::new(self);
+        for part in message {
+            match part {
+                MessagePart::Text(text) => {
+                    let mut explicit_lines = text.split('\n');
+                    if let Some(first) = explicit_lines.next() {
+                        writer.push_text(first);
+                    };
+                    for line in explicit_lines {
+                        writer.finish_line();
+                        writer.push_text(line);
+                    }
+                }
+                MessagePart::Placeholder(placeholder) => {
+                    if self.page_breaks.contains(&placeholder.name) {
+                        boxes.push(writer.finish_box());
+                    } else if let Some(text) = self.placeholder_texts.get(&placeholder.name) {
+                        // a variable text can't be split between two lines
+                        writer.word_width += self.glyph_widths.text_width(text);
+                    }
+                }
+            }
+        }
+        boxes.push(writer.finish_box());
+        boxes
+    }
+
+    /// Return the problems of the layout of a message
+    pub fn check(&self, message: &Message) -> Vec<LayoutProblem> {
+        let mut problems = Vec::new();
+        for (box_index, lines) in self.boxes(message).into_iter().enumerate() {
+            for (line_index, width) in lines.iter().enumerate() {
+                if let Some(max_width) = self.max_width {
+                    if *width > max_width {
+                        problems.push(LayoutProblem::TooWide {
+                            box_number: box_index + 1,
+                            line_number: line_index + 1,
+                            width: *width,
+                            max_width,
+                        });
+                    };
+                };
+            }
+            if let Some(max_lines) = self.max_lines {
+                if lines.len() > max_lines {
+                    problems.push(LayoutProblem::TooManyLines {
+                        box_number: box_index + 1,
+                        lines: lines.len(),
+                        max_lines,
+                    });
+                };
+            };
+        }
+        problems
+    }
+}
+
+/// Compute the width of the lines of a box, wrapping them if needed
+struct LineWriter<'a> {
+    layout: &'a Layout,
+    /// The width of the finished lines of the current box
+    lines: Vec<u32>,
+    /// The width of the current line, without the current word
+    line_width: u32,
+    /// The width of the current word
+    word_width: u32,
+    /// The width of the spaces between the end of the line and the current word
+    space_width: u32,
+}
+
+impl<'a> LineWriter<'a> {
+    fn new(layout: &'a Layout) -> Self {
+        Self {
+            layout,
+            lines: Vec::new(),
+            line_width: 0,
+            word_width: 0,
+            space_width: 0,
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        for character in text.chars() {
+            let width = self.layout.glyph_widths.width(character);
+            // without wrapping, the whole line is a single word
+            if character == ' ' && self.layout.wrap {
+                self.end_word();
+                self.space_width += width;
+            } else {
+                self.word_width += width;
+            }
+        }
+    }
+
+    /// Add the current word to the line, starting a new line before it if it doesn't fit
+    fn end_word(&mut self) {
+        if self.word_width == 0 {
+            return;
+        };
+        let width_with_word = self.line_width + self.space_width + self.word_width;
+        match self.layout.max_width {
+            Some(max_width)
+                if self.layout.wrap && self.line_width > 0 && width_with_word > max_width =>
+            {
+                self.lines.push(self.line_width);
+                self.line_width = self.word_width;
+            }
+            _ => self.line_width = width_with_word,
+        };
+        self.word_width = 0;
+        self.space_width = 0;
+    }
+
+    /// Finish the current line. The spaces at its end are ignored.
+    fn finish_line(&mut self) {
+        self.end_word();
+        self.lines.push(self.line_width);
+        self.line_width = 0;
+        self.space_width = 0;
+    }
+
+    /// Finish the current box, returning the width of its lines
+    fn finish_box(&mut self) -> Vec<u32> {
+        self.finish_line();
+        std::mem::take(&mut self.lines)
+    }
+}
+
+/// A problem in the layout of a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutProblem {
+    /// A line of a box is wider than the limit, even once wrapped
+    TooWide {
+        box_number: usize,
+        line_number: usize,
+        width: u32,
+        max_width: u32,
+    },
+    /// A box has more lines than the limit
+    TooManyLines {
+        box_number: usize,
+        lines: usize,
+        max_lines: usize,
+    },
+}
+
+impl fmt::Display for LayoutProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooWide {
+                box_number,
+                line_number,
+                width,
+                max_width,
+            } => write!(
+                f,
+                "box {}, line {}: the line is {} wide, but the maximum is {}",
+                box_number, line_number, width, max_width
+            ),
+            Self::TooManyLines {
+                box_number,
+                lines,
+                max_lines,
+            } => write!(
+                f,
+                "box {}: the box has {} lines, but the maximum is {}",
+                box_number, lines, max_lines
+            ),
+        }
+    }
+}
+
+/// A problem in the layout of a translation, with the messages and the po entry it come from
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutIssue {
+    /// The ``(source file, hash)`` of the messages with this translation
+    pub messages: Vec<(String, u32)>,
+    pub po_file: Option<PathBuf>,
+    /// The line of the ``msgstr`` in the po file
+    pub po_line: Option<usize>,
+    pub problem: LayoutProblem,
+}
+
+impl fmt::Display for LayoutIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_po_location(f, &self.po_file, self.po_line, &self.messages)?;
+        write!(f, " {}", self.problem)
+    }
+}
+
+impl GettextWriter {
+    /// Check the layout of every translation. Translations that can't be parsed are ignored, as they are
+    /// reported by [`GettextWriter::check_encoding`].
+    pub fn check_layout(&self, layout: &Layout, table: &PlaceholderTable) -> Vec<LayoutIssue> {
+        let mut issues = Vec::new();
+        for checked in self.po_translations() {
+            let message = match table.parse(checked.translation) {
+                Ok(message) => message,
+                Err(_) => continue,
+            };
+            for problem in layout.check(&message) {
+                issues.push(LayoutIssue {
+                    messages: checked.messages.clone(),
+                    po_file: checked.po_file.clone(),
+                    po_line: checked.po_line,
+                    problem,
+                });
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A layout counting the widths in characters
+    fn layout(max_width: u32) -> Layout {
+        let mut layout = Layout::new(GlyphWidths::monospace(1));
+        layout.max_width = Some(max_width);
+        layout
+    }
+
+    fn boxes(layout: &Layout, text: &str) -> Vec<Vec<u32>> {
+        layout.boxes(&Message::parse(text).unwrap())
+    }
+
+    #[test]
+    fn parse_glyph_widths() {
+        let widths =
+            GlyphWidths::parse("# the font of the dialogue box\n\n6 abc\n3 il U+0020\n8 U+\n")
+                .unwrap();
+        assert_eq!(widths.width('a'), 6);
+        assert_eq!(widths.width('i'), 3);
+        assert_eq!(widths.width(' '), 3);
+        assert_eq!(widths.width('U'), 8);
+        assert_eq!(widths.width('+'), 8);
+        // the other characters are as wide as the widest glyph
+        assert_eq!(widths.width('z'), 8);
+        assert_eq!(widths.text_width("ai z"), 20);
+
+        let widths = GlyphWidths::parse("6 abc\ndefault 4").unwrap();
+        assert_eq!(widths.width('z'), 4);
+
+        assert!(matches!(
+            GlyphWidths::parse("6 a\nwide abc"),
+            Err(LayoutError::MalformedWidth(2, _))
+        ));
+        assert!(matches!(
+            GlyphWidths::parse("6"),
+            Err(LayoutError::MalformedWidth(1, _))
+        ));
+        assert!(matches!(
+            GlyphWidths::parse("6 U+D800"),
+            Err(LayoutError::InvalidCharacter(1, _))
+        ));
+    }
+
+    #[test]
+    fn lines_are_wrapped_at_spaces() {
+        let layout = layout(10);
+        assert_eq!(boxes(&layout, ""), [[0]]);
+        assert_eq!(boxes(&layout, "Hello"), [[5]]);
+        // the space before a wrapped word is dropped
+        assert_eq!(boxes(&layout, "Hello world again"), [[5, 5, 5]]);
+        assert_eq!(boxes(&layout, "Hello you"), [[9]]);
+        assert_eq!(boxes(&layout, "Hello you!"), [[10]]);
+        assert_eq!(boxes(&layout, "Hello you!!"), [[5, 5]]);
+        // the spaces at the end of a line take no room
+        assert_eq!(boxes(&layout, "Hello you!    "), [[10]]);
+        assert_eq!(boxes(&layout, "a  b"), [[4]]);
+        // a word too long is left alone on its line, even the first one
+        assert_eq!(boxes(&layout, "Incomprehensible"), [[16]]);
+        assert_eq!(boxes(&layout, "a Incomprehensible b"), [[1, 16, 1]]);
+    }
+
+    #[test]
+    fn explicit_line_breaks_and_page_breaks() {
+        let mut layout = layout(10);
+        layout.page_breaks.insert("K".to_string());
+        assert_eq!(boxes(&layout, "Hello  \nyou"), [[5, 3]]);
+        assert_eq!(boxes(&layout, "a\n\nb"), [[1, 0, 1]]);
+        assert_eq!(
+            boxes(&layout, "Hello world[K]Bye\nnow[K]"),
+            vec![vec![5, 5], vec![3, 3], vec![0]]
+        );
+        // a page break end the current word
+        assert_eq!(boxes(&layout, "ab[K]cd"), [[2], [2]]);
+        // the other placeholders take no room
+        assert_eq!(boxes(&layout, "ab[CENTER]cd"), [[4]]);
+    }
+
+    #[test]
+    fn placeholder_texts() {
+        let mut layout = layout(10);
+        assert!(layout.add_placeholder_text("PARTNERNAME=Mmmmmmmmmm"));
+        assert!(layout.add_placeholder_text(" HERO =Mmm"));
+        assert!(!layout.add_placeholder_text("PARTNERNAME"));
+        assert!(!layout.add_placeholder_text("=Mmm"));
+        assert_eq!(layout.placeholder_texts["HERO"], "Mmm");
+        assert_eq!(boxes(&layout, "[HERO] is here"), [[6, 4]]);
+        // a placeholder text is part of the word it is in
+        assert_eq!(boxes(&layout, "Hi [HERO]!"), [[7]]);
+        assert_eq!(boxes(&layout, "Hi [PARTNERNAME]"), [[2, 10]]);
+        assert_eq!(boxes(&layout, "[PARTNERNAME]!"), [[11]]);
+
+        layout.glyph_widths = GlyphWidths::monospace(2);
+        layout.max_width = Some(20);
+        assert_eq!(boxes(&layout, "Hi [HERO]!"), [[14]]);
+    }
+
+    #[test]
+    fn lines_as_written_without_wrapping() {
+        let mut layout = layout(10);
+        layout.wrap = false;
+        assert_eq!(boxes(&layout, "Hello world again"), [[17]]);
+        // the spaces are counted, even at the end of the line
+        assert_eq!(boxes(&layout, "Hello  \nyou"), [[7, 3]]);
+        assert_eq!(
+            layout.check(&Message::parse("Hello world again\nShort").unwrap()),
+            vec![LayoutProblem::TooWide {
+                box_number: 1,
+                line_number: 1,
+                width: 17,
+                max_width: 10,
+            }]
+        );
+    }
+
+    #[test]
+    fn check_limits() {
+        let mut layout = layout(10);
+        layout.max_lines = Some(2);
+        layout.page_breaks.insert("K".to_string());
+        let problems =
+            layout.check(&Message::parse("Hello world again[K]Incomprehensible[K]fine").unwrap());
+        assert_eq!(
+            problems,
+            vec![
+                LayoutProblem::TooManyLines {
+                    box_number: 1,
+                    lines: 3,
+                    max_lines: 2,
+                },
+                LayoutProblem::TooWide {
+                    box_number: 2,
+                    line_number: 1,
+                    width: 16,
+                    max_width: 10,
+                },
+            ]
+        );
+        assert_eq!(
+            problems[1].to_string(),
+            "box 2, line 1: the line is 16 wide, but the maximum is 10"
+        );
+        assert!(layout
+            .check(&Message::parse("Hello world[K]again").unwrap())
+            .is_empty());
+    }
+}
@@ -1,30 +1,116 @@
-use std::{borrow::Cow, collections::BTreeSet, str::FromStr};
-use thiserror::Error;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+mod po;
+pub use po::{
+    parse_po, unescape_gettext_string, write_po, write_po_entry, write_po_to, PoEntry, PoError,
+    PoErrors, PoParser, PoReadError, PoReader, PoWarning,
+};
+
+mod update;
+pub use update::UpdateReport;
+
+mod header;
+pub use header::PoHeader;
+
+mod pipeline;
+pub use pipeline::{
+    build_farc, build_farc_on_base, build_folder, build_folder_on_base, extract_farc,
+    extract_folder, extract_message_farc, hash_of_file_name, open_message_farc, unnamed_file_name,
+    Catalog, PipelineError, NAME_SORT_FILE,
+};
+
+mod storage;
+pub use storage::{PoStorageMode, STANDARD_STREAM};
+
+mod grouping;
+pub use grouping::{GroupingError, PoGrouping};
+
+mod discriminator;
+pub use discriminator::{Discriminator, DiscriminatorError, DiscriminatorRule, RuleMatcher};
+
+mod message;
+pub use message::{Message, MessageError, MessagePart, Placeholder, PlaceholderTable};
+
+mod charset;
+pub use charset::{CharacterProblem, Charset, EncodingIssue, EncodingProblem};
+
+mod layout;
+pub use layout::{GlyphWidths, Layout, LayoutError, LayoutIssue, LayoutProblem};
+
+mod lint;
+pub use lint::{LintIssue, LintKind, Linter};
+
+mod ambiguity;
+pub use ambiguity::{write_ambiguity_review, AmbiguityCriteria, AmbiguityReason, AmbiguousString};
+
+mod project;
+pub use project::{
+    GameConfig, GameFormat, LanguageConfig, PoConfig, Project, ProjectError, PROJECT_FILE_NAME,
+};
 
 pub struct Entry {
+    /// The original text (the msgid)
     pub text: String,
+    /// The translated text (the msgstr), if this entry is translated
+    pub translation: Option<String>,
+    /// The gettext context (msgctxt), that separate this entry from others with the same text
+    pub context: Option<String>,
+    /// true if the translation is marked as fuzzy
+    pub fuzzy: bool,
     pub hash: u32,
     pub unk: u32,
     pub source_file: String,
+    /// The position of the message in its message file, if known
+    pub index: Option<usize>,
+    /// The po file this entry was read from, if any
+    pub po_file: Option<PathBuf>,
+    /// The line of the ``msgstr`` of this entry in its po file, if it was read from one
+    pub po_line: Option<usize>,
 }
 
 impl Entry {
     pub fn new(text: String, hash: u32, unk: u32, source_file: String) -> Self {
         Self {
             text,
+            translation: None,
+            context: None,
+            fuzzy: false,
             hash,
             unk,
             source_file,
+            index: None,
+            po_file: None,
+            po_line: None,
         }
     }
+
+    /// Return the text that should be used in game: the translation if it exist, the original text otherwise.
+    pub fn translated_text(&self) -> &str {
+        self.translation.as_deref().unwrap_or(&self.text)
+    }
+
+    /// The context given to this entry when it need to be translated separately
+    fn own_context(&self) -> String {
+        format!("{} {}", self.source_file, self.hash)
+    }
 }
 
-const DISCRIMINATOR: &str = "ŧdiscrimatorŧ";
+/// Suffix that was appended to the msgid of discriminated strings before they got a `msgctxt`.
+/// Only used to read older po files.
+pub(crate) const DISCRIMINATOR: &str = "ŧdiscrimatorŧ";
 
 pub struct EntryNoText<'a> {
     pub hash: u32,
     pub unk: u32,
     pub source_file: &'a str,
+    pub index: Option<usize>,
 }
 
 impl<'a> EntryNoText<'a> {
@@ -35,26 +121,44 @@ impl<'a> EntryNoText<'a> {
                 hash: entry.hash,
                 unk: entry.unk,
                 source_file: &entry.source_file,
+                index: entry.index,
             },
             &entry.text,
         )
     }
 }
 
-#[derive(Debug, Error)]
-/// A non fatal parsing error of Po file. The user should likely be informed of these
-pub enum PoWarning {
-    #[error("the line {0} start with the unknown symbol {1:?}")]
-    LineTypeUnknown(usize, String),
-    #[error("the line {0} end with an unclosed quote")]
-    UnclosedQuote(usize),
-    #[error("the line {0} end with an escape character")]
-    UnfinishedEscape(usize),
+/// What to do with translations marked with the ``fuzzy`` flag
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FuzzyPolicy {
+    /// Use the fuzzy translation as if it was a normal one
+    Use,
+    /// Ignore the fuzzy translation, and use the source text
+    Source,
+    /// Fuzzy translations shouldn't be present. Each of them is reported as a [`PoError::FuzzyTranslation`],
+    /// as the translation isn't finished.
+    Fail,
+}
+
+impl FromStr for FuzzyPolicy {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "use" => Ok(Self::Use),
+            "source" => Ok(Self::Source),
+            "fail" => Ok(Self::Fail),
+            _ => Err("the fuzzy policy should be either \"use\", \"source\" or \"fail\""),
+        }
+    }
 }
 
 pub struct GettextWriter {
     pub entries: Vec<Entry>,
-    discriminated: BTreeSet<String>,
+    /// The header of the po file. A template header is written by [`GettextWriter::to_pot`] if there is none.
+    pub header: Option<PoHeader>,
+    discriminator: Discriminator,
 }
 
 pub fn escape_string_for_gettext(text: &str) -> String {
@@ -79,246 +183,693 @@ pub fn escape_string_for_gettext(text: &str) -> String {
     result
 }
 
+/// A message translated by a po entry
+pub(crate) struct PoLocation {
+    pub source_file: String,
+    pub hash: u32,
+    pub unk: u32,
+    pub index: Option<usize>,
+}
+
+/// Parse a location comment of the form ``<source file> <hash> <unk>``, found at the given line. It is followed
+/// by `` <index>``, the position of the message in its message file, for the locations that aren't the first of
+/// their po entry: the other ones are read back in the order of the file, but those are grouped with the first
+/// message with the same text.
+pub(crate) fn parse_location(comment: &str, line_nb: usize) -> Result<PoLocation, PoError> {
+    // the comment is preceded by "#. "
+    const COLUMN_OFFSET: usize = 4;
+    let mut line_splited = comment.split(' ');
+    let (source_file, hash, unk, index) = match (
+        line_splited.next(),
+        line_splited.next(),
+        line_splited.next(),
+        line_splited.next(),
+        line_splited.next(),
+    ) {
+        (Some(source_file), Some(hash), Some(unk), index, None) => (source_file, hash, unk, index),
+        _ => {
+            return Err(PoError::MalformedLocation(
+                line_nb,
+                COLUMN_OFFSET,
+                comment.to_string(),
+            ))
+        }
+    };
+    let parse_number = |text: &str, column: usize| {
+        u32::from_str(text).map_err(|err| {
+            PoError::InvalidNumber(line_nb, COLUMN_OFFSET + column, text.to_string(), err)
+        })
+    };
+    let unk_column = source_file.chars().count() + hash.chars().count() + 2;
+    Ok(PoLocation {
+        source_file: source_file.to_string(),
+        hash: parse_number(hash, source_file.chars().count() + 1)?,
+        unk: parse_number(unk, unk_column)?,
+        index: match index {
+            Some(index) => {
+                Some(parse_number(index, unk_column + unk.chars().count() + 1)? as usize)
+            }
+            None => None,
+        },
+    })
+}
+
+/// Prefix of the flags storing the ``unk`` value of a location, as ``pmd-unk:<reference>=<unk>``.
+/// It is written for every location, even with an ``unk`` of 0, so a missing flag can be reported.
+const UNK_FLAG_PREFIX: &str = "pmd-unk:";
+
+/// Prefix of the flags storing the position of a message in its message file, as ``pmd-index:<reference>=<index>``.
+/// It is only written for the locations that aren't the first of their po entry: the other ones are read back in
+/// the order of the file, but those are grouped with the first message with the same text.
+const INDEX_FLAG_PREFIX: &str = "pmd-index:";
+
+/// Return true if this flag store a value of a location, like its ``unk`` value or its index
+pub(crate) fn is_location_flag(flag: &str) -> bool {
+    flag.starts_with(UNK_FLAG_PREFIX) || flag.starts_with(INDEX_FLAG_PREFIX)
+}
+
+/// Format the reference of a message, like ``script.bin:0x1234ABCD``
+fn format_reference(source_file: &str, hash: u32) -> String {
+    format!("{}:0x{:08X}", source_file, hash)
+}
+
+/// Write the location of a translation: its po file and line (if known), followed by the messages it translate
+/// and a ``:``, like ``fr.po:12: script.bin:0x1234ABCD:``
+pub(crate) fn write_po_location(
+    f: &mut fmt::Formatter<'_>,
+    po_file: &Option<PathBuf>,
+    po_line: Option<usize>,
+    messages: &[(String, u32)],
+) -> fmt::Result {
+    if let Some(po_file) = po_file {
+        write!(f, "{}:", po_file.display())?;
+    };
+    if let Some(po_line) = po_line {
+        write!(f, "{}:", po_line)?;
+    };
+    let messages: Vec<String> = messages
+        .iter()
+        .map(|(source_file, hash)| format_reference(source_file, *hash))
+        .collect();
+    write!(f, " {}:", messages.join(", "))
+}
+
+/// A translation of a catalog, with the po entry it was read from and the messages sharing it
+pub(crate) struct PoTranslation<'a> {
+    pub po_file: &'a Option<PathBuf>,
+    pub po_line: Option<usize>,
+    pub translation: &'a str,
+    /// The ``(source file, hash)`` of the messages with this translation
+    pub messages: Vec<(String, u32)>,
+}
+
+/// Parse a reference of the form ``<source file>:0x<hash>``, found at the given line
+fn parse_reference(reference: &str, line_nb: usize) -> Result<(String, u32), PoError> {
+    // the reference is preceded by "#: "
+    const COLUMN_OFFSET: usize = 4;
+    reference
+        .rsplit_once(':')
+        .and_then(|(source_file, hash)| {
+            let hash = hash.strip_prefix("0x")?;
+            Some((source_file.to_string(), u32::from_str_radix(hash, 16).ok()?))
+        })
+        .ok_or_else(|| PoError::MalformedReference(line_nb, COLUMN_OFFSET, reference.to_string()))
+}
+
+/// Read the flags of the form ``<prefix><reference>=<number>`` of a po entry, by location. The malformed ones are
+/// added to ``errors``.
+fn read_location_flags(
+    po_entry: &PoEntry,
+    prefix: &str,
+    errors: &mut Vec<PoError>,
+) -> HashMap<(String, u32), u32> {
+    let mut values = HashMap::new();
+    for flag in po_entry
+        .flags
+        .iter()
+        .filter(|flag| flag.starts_with(prefix))
+    {
+        let (reference, value) = flag[prefix.len()..].rsplit_once('=').unwrap_or((flag, ""));
+        match (
+            parse_reference(reference, po_entry.line),
+            u32::from_str(value),
+        ) {
+            (Ok(location), Ok(value)) => {
+                values.insert(location, value);
+            }
+            (Err(err), _) => errors.push(err),
+            (_, Err(err)) => errors.push(PoError::InvalidNumber(
+                po_entry.line,
+                1,
+                value.to_string(),
+                err,
+            )),
+        }
+    }
+    values
+}
+
+/// Return the locations of a po entry, with the errors of the malformed ones and a warning for each reference
+/// without a ``pmd-unk`` flag. They are read from the references, or from the extracted comments
+/// (``<source file> <hash> <unk>``) of older files without references.
+fn read_po_entry_locations(po_entry: &PoEntry) -> (Vec<PoLocation>, Vec<PoWarning>, Vec<PoError>) {
+    let mut locations = Vec::new();
+    let mut warnings = Vec::new();
+    let mut errors = Vec::new();
+    if po_entry.references.is_empty() {
+        for (comment, line_nb) in po_entry.locations_comment() {
+            match parse_location(comment, line_nb) {
+                Ok(location) => locations.push(location),
+                Err(err) => errors.push(err),
+            }
+        }
+        return (locations, warnings, errors);
+    };
+
+    let unks = read_location_flags(po_entry, UNK_FLAG_PREFIX, &mut errors);
+    let indexes = read_location_flags(po_entry, INDEX_FLAG_PREFIX, &mut errors);
+    for (reference, line_nb) in po_entry.references_with_line() {
+        match parse_reference(reference, line_nb) {
+            Ok(location) => {
+                let unk = match unks.get(&location) {
+                    Some(unk) => *unk,
+                    None => {
+                        warnings.push(PoWarning::MissingLocationFlags(
+                            line_nb,
+                            reference.to_string(),
+                        ));
+                        0
+                    }
+                };
+                locations.push(PoLocation {
+                    unk,
+                    index: indexes.get(&location).map(|index| *index as usize),
+                    source_file: location.0,
+                    hash: location.1,
+                })
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+    (locations, warnings, errors)
+}
+
+/// Return the ``(source_file, hash, unk)`` locations of a po entry. Malformed locations are ignored.
+pub fn po_entry_locations(po_entry: &PoEntry) -> Vec<(String, u32, u32)> {
+    read_po_entry_locations(po_entry)
+        .0
+        .into_iter()
+        .map(|location| (location.source_file, location.hash, location.unk))
+        .collect()
+}
+
 impl GettextWriter {
     pub fn new(discriminated: Vec<String>) -> Self {
-        Self {
+        let mut result = Self {
             entries: Vec::new(),
-            discriminated: discriminated
-                .into_iter()
-                .map(|x| x.to_lowercase())
-                .collect(),
-        }
+            header: None,
+            discriminator: Discriminator::default(),
+        };
+        result.set_discriminated(discriminated);
+        result
     }
 
-    pub fn to_pot(&self) -> String {
-        let mut result = String::new();
+    /// Set the list of phrases that have multiple meaning. Entries containing them are translated separately.
+    pub fn set_discriminated(&mut self, discriminated: Vec<String>) {
+        self.discriminator = Discriminator::from_substrings(discriminated);
+    }
+
+    /// Set the rules deciding which entries are translated separately
+    pub fn set_discriminator(&mut self, discriminator: Discriminator) {
+        self.discriminator = discriminator;
+    }
+
+    /// Return the entries that are translated separately because of a discriminator rule, with this rule
+    pub fn discrimination_report(&self) -> Vec<(&Entry, &DiscriminatorRule)> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.context.is_none())
+            .filter_map(|entry| Some((entry, self.discriminator.splitting_rule(entry)?)))
+            .collect()
+    }
 
+    /// Return the deduplicated entries, as they should be written in a po file.
+    /// Entries with the same text but a different translation are given their own context.
+    pub fn to_po_entries(&self) -> Vec<PoEntry> {
         // deduplicate the strings
 
-        let mut translate_string: Vec<(Cow<str>, Vec<EntryNoText>)> = Vec::new();
+        struct Group<'a> {
+            context: Option<String>,
+            text: &'a str,
+            translation: Option<&'a str>,
+            fuzzy: bool,
+            sources: Vec<EntryNoText<'a>>,
+        }
+
+        let mut translate_string: Vec<Group> = Vec::new();
+        // the position of the first group with a given context and text
+        let mut group_index: HashMap<(Option<String>, &str), usize> = HashMap::new();
 
         for entry in &self.entries {
             let (entry_no_text, text) = EntryNoText::from_entry(entry);
-            let mut should_be_discriminated = false;
-            let text_lower = text.to_lowercase();
-            for discriminator in &self.discriminated {
-                if text_lower.contains(discriminator) {
-                    should_be_discriminated = true;
-                }
-            }
-            let text = if should_be_discriminated {
-                Cow::from(format!(
-                    "{}{} {} {}",
-                    text, DISCRIMINATOR, entry_no_text.source_file, entry_no_text.hash
-                ))
-            } else {
-                Cow::from(text)
+            let mut context = entry.context.clone();
+            if context.is_none() && self.discriminator.splitting_rule(entry).is_some() {
+                context = Some(entry.own_context());
             };
 
-            let mut insert_at = None;
-            for entry in translate_string.iter_mut() {
-                if entry.0 == text {
-                    insert_at = Some(entry);
-                    break;
+            let translation = entry.translation.as_deref();
+            let same_text = group_index.get(&(context.clone(), text)).copied();
+            let insert_at = match same_text {
+                Some(position)
+                    if translate_string[position].translation == translation
+                        && translate_string[position].fuzzy == entry.fuzzy =>
+                {
+                    Some(position)
                 }
-            }
-            if let Some(good) = insert_at {
-                good.1.push(entry_no_text)
+                Some(_) => {
+                    // same text, but translated differently
+                    context = Some(entry.own_context());
+                    None
+                }
+                None => None,
+            };
+            if let Some(position) = insert_at {
+                translate_string[position].sources.push(entry_no_text)
             } else {
-                translate_string.push((text, vec![entry_no_text]))
+                group_index
+                    .entry((context.clone(), text))
+                    .or_insert(translate_string.len());
+                translate_string.push(Group {
+                    context,
+                    text,
+                    translation,
+                    fuzzy: entry.fuzzy,
+                    sources: vec![entry_no_text],
+                })
             }
         }
 
-        // create the po file
+        // create the po entries
 
-        for entry in translate_string {
-            for source in entry.1 {
-                result.push_str(&format!(
-                    "#. {} {} {}\n",
-                    source.source_file, source.hash, source.unk
-                ));
-            }
-            result.push_str(&format!(
-                "msgid {}\nmsgstr \"\"\n\n",
-                escape_string_for_gettext(if entry.0 == "" { " " } else { entry.0.as_ref() })
-            ));
-        }
+        self.header
+            .iter()
+            .map(|header| header.to_po_entry())
+            .chain(translate_string.into_iter().map(|group| {
+                PoEntry {
+                    references: group
+                        .sources
+                        .iter()
+                        .map(|source| format_reference(source.source_file, source.hash))
+                        .collect(),
+                    flags: group
+                        .fuzzy
+                        .then(|| "fuzzy".to_string())
+                        .into_iter()
+                        .chain(group.sources.iter().map(|source| {
+                            format!(
+                                "{}{}={}",
+                                UNK_FLAG_PREFIX,
+                                format_reference(source.source_file, source.hash),
+                                source.unk
+                            )
+                        }))
+                        .chain(group.sources.iter().skip(1).filter_map(|source| {
+                            Some(format!(
+                                "{}{}={}",
+                                INDEX_FLAG_PREFIX,
+                                format_reference(source.source_file, source.hash),
+                                source.index?
+                            ))
+                        }))
+                        .collect(),
+                    msgctxt: group.context,
+                    msgid: if group.text.is_empty() {
+                        " ".to_string()
+                    } else {
+                        group.text.to_string()
+                    },
+                    msgstr: vec![group.translation.unwrap_or_default().to_string()],
+                    ..Default::default()
+                }
+            }))
+            .collect()
+    }
 
-        result
+    /// Write the entries as a po file, with their translations
+    pub fn to_po(&self) -> String {
+        write_po(&self.to_po_entries())
     }
 
-    pub fn from_po(file: String) -> (Self, Vec<PoWarning>) {
-        let mut warning = Vec::new();
-        let mut result = GettextWriter {
-            entries: Vec::new(),
-            discriminated: BTreeSet::default(),
+    /// Write the entries as a po template, ignoring their translations
+    pub fn to_pot(&self) -> String {
+        write_po(&self.to_pot_entries())
+    }
+
+    /// Write the entries as a po file, with their translations, to ``writer``
+    pub fn write_po_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        write_po_to(&self.to_po_entries(), writer)
+    }
+
+    /// Write the entries as a po template, ignoring their translations, to ``writer``
+    pub fn write_pot_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        write_po_to(&self.to_pot_entries(), writer)
+    }
+
+    fn to_pot_entries(&self) -> Vec<PoEntry> {
+        let mut entries = self.to_po_entries();
+        if self.header.is_none() {
+            entries.insert(
+                0,
+                PoHeader::new_template("PACKAGE VERSION".to_string(), String::new()).to_po_entry(),
+            );
         };
+        for entry in entries.iter_mut().filter(|entry| !entry.is_header()) {
+            entry.flags.retain(|flag| is_location_flag(flag));
+            entry.msgstr = vec![String::new()];
+        }
+        entries
+    }
+
+    /// Return true if at least one entry is translated
+    pub fn has_translation(&self) -> bool {
+        self.entries.iter().any(|entry| entry.translation.is_some())
+    }
 
-        let mut msgid = String::new();
-        let mut msgstr = String::new();
-        let mut comment = Vec::new();
-        pub enum Parsing {
-            Msgid,
-            Msgstr,
+    /// Parse a po file. Every translation marked as fuzzy will be reported with a [`PoWarning::FuzzyTranslation`].
+    /// If the file contain errors, all of them are returned.
+    pub fn from_po(
+        file: String,
+        fuzzy_policy: FuzzyPolicy,
+    ) -> Result<(Self, Vec<PoWarning>), PoErrors> {
+        let (po_entries, mut warnings, mut errors) = parse_po(&file);
+        let mut result = GettextWriter::new(Vec::new());
+
+        for po_entry in po_entries {
+            match result.push_po_entry(po_entry, fuzzy_policy) {
+                Ok(mut entry_warnings) => warnings.append(&mut entry_warnings),
+                Err(mut entry_errors) => errors.append(&mut entry_errors),
+            };
         }
-        let mut parsing: Option<Parsing> = None;
 
-        #[derive(Clone, PartialEq)]
-        pub enum Phase {
-            Pre,
-            Final,
+        if errors.is_empty() {
+            Ok((result, warnings))
+        } else {
+            errors.sort_by_key(|error| error.line());
+            Err(PoErrors(errors))
         }
-        let mut phase = Phase::Pre; //passing from data to comment mean we are into the next part
+    }
 
-        let mut push_current_translation =
-            |msgid_input: &mut String, msgstr: &mut String, comment: &mut Vec<String>| {
-                if msgid_input.is_empty() && comment.is_empty() {
-                    return;
-                };
-                if msgid_input == " " {
-                    msgid_input.clear();
-                    msgstr.clear();
-                };
-                let msgid = msgid_input.split(DISCRIMINATOR).next().unwrap().to_string();
-                if msgstr.is_empty() {
-                    *msgstr = msgid.clone();
-                };
-                if comment.is_empty() {
-                    todo!("the comment for \"{}\" is empty", msgid_input);
-                };
-                for comment_line in comment.iter() {
-                    let mut line_splited = comment_line.split(' ');
-                    let file_source = line_splited.next().unwrap();
-                    let hash = u32::from_str(line_splited.next().unwrap()).unwrap();
-                    let unk1 = u32::from_str(line_splited.next().unwrap()).unwrap();
-                    result.entries.push(Entry {
-                        text: msgstr.clone(),
-                        hash,
-                        unk: unk1,
-                        source_file: file_source.to_string(),
-                    });
-                }
+    /// Read a po file from ``reader``, one entry at a time. See [`GettextWriter::from_po`].
+    pub fn from_po_reader<R: BufRead>(
+        reader: R,
+        fuzzy_policy: FuzzyPolicy,
+    ) -> Result<(Self, Vec<PoWarning>), PoReadError> {
+        let mut po_reader = PoReader::new(reader);
+        let mut result = GettextWriter::new(Vec::new());
+        let mut entry_warnings = Vec::new();
+        let mut errors = Vec::new();
 
-                msgstr.clear();
-                msgid_input.clear();
-                comment.clear();
+        for po_entry in po_reader.by_ref() {
+            match result.push_po_entry(po_entry?, fuzzy_policy) {
+                Ok(mut warnings) => entry_warnings.append(&mut warnings),
+                Err(mut entry_errors) => errors.append(&mut entry_errors),
             };
+        }
 
-        for (line_nb, line) in file.lines().enumerate() {
-            if line.is_empty() {
-                continue;
-            }
-            if Some('\"') == line.chars().next() {
-            } else if let Some(first_command) = line.split(' ').next() {
-                let next_phase = match first_command {
-                    "msgstr" => {
-                        parsing = Some(Parsing::Msgstr);
-                        Phase::Final
-                    }
-                    "msgid" => {
-                        parsing = Some(Parsing::Msgid);
-                        Phase::Pre
-                    }
-                    "#." => Phase::Pre,
-                    _ => {
-                        warning.push(PoWarning::LineTypeUnknown(
-                            line_nb,
-                            first_command.to_string(),
-                        ));
-                        phase.clone()
-                    }
-                };
-                if phase == Phase::Final && next_phase == Phase::Pre {
-                    push_current_translation(&mut msgid, &mut msgstr, &mut comment);
-                };
-                if first_command == "#." {
-                    comment.push(line.chars().skip(3).collect::<String>());
-                };
-                phase = next_phase;
-            } else {
-                continue;
+        errors.append(&mut po_reader.take_errors());
+        if errors.is_empty() {
+            let mut warnings = po_reader.take_warnings();
+            warnings.append(&mut entry_warnings);
+            Ok((result, warnings))
+        } else {
+            errors.sort_by_key(|error| error.line());
+            Err(PoErrors(errors).into())
+        }
+    }
+
+    /// Add the translated entries described by this po entry. Obsolete entries are ignored, and the header is
+    /// stored in [`GettextWriter::header`] if there isn't one yet.
+    /// Return a warning if the entry has a fuzzy translation, and one for each reference without its flags.
+    pub fn push_po_entry(
+        &mut self,
+        po_entry: PoEntry,
+        fuzzy_policy: FuzzyPolicy,
+    ) -> Result<Vec<PoWarning>, Vec<PoError>> {
+        if po_entry.obsolete {
+            return Ok(Vec::new());
+        };
+        if po_entry.is_header() {
+            if self.header.is_none() {
+                self.header = PoHeader::from_po_entry(&po_entry);
+            };
+            return Ok(Vec::new());
+        };
+        let (locations, mut warnings, errors) = read_po_entry_locations(&po_entry);
+        if !errors.is_empty() {
+            return Err(errors);
+        };
+        if locations.is_empty() {
+            return Err(vec![PoError::MissingLocation(
+                po_entry.line,
+                1,
+                po_entry.msgid,
+            )]);
+        };
+        let is_fuzzy = po_entry.has_flag("fuzzy") && po_entry.translation().is_some();
+        if is_fuzzy && fuzzy_policy == FuzzyPolicy::Fail {
+            return Err(vec![PoError::FuzzyTranslation(
+                po_entry.line,
+                1,
+                po_entry.msgid,
+            )]);
+        };
+        if is_fuzzy {
+            warnings.push(PoWarning::FuzzyTranslation(
+                po_entry.line,
+                po_entry.msgid.clone(),
+            ));
+        };
+        let use_translation = !is_fuzzy || fuzzy_policy == FuzzyPolicy::Use;
+        let (msgid, msgstr, context) = if po_entry.msgid == " " {
+            (String::new(), None, po_entry.msgctxt.clone())
+        } else {
+            // older files store the context in the msgid rather than in a msgctxt
+            let mut msgid_splited = po_entry.msgid.splitn(2, DISCRIMINATOR);
+            let msgid = msgid_splited.next().unwrap().to_string();
+            let context = match msgid_splited.next() {
+                Some(legacy_context) => Some(legacy_context.trim().to_string()),
+                None => po_entry.msgctxt.clone(),
             };
+            (
+                msgid,
+                po_entry
+                    .translation()
+                    .filter(|_| use_translation)
+                    .map(|x| x.to_string()),
+                context,
+            )
+        };
+        let fuzzy = is_fuzzy && msgstr.is_some();
+        let po_line = po_entry.msgstr_line(0);
 
-            if let Some(what_is_parsed) = &parsing {
-                let line_no_parentesis = match what_is_parsed {
-                    Parsing::Msgid => &mut msgid,
-                    Parsing::Msgstr => &mut msgstr,
-                };
+        for location in locations {
+            self.entries.push(Entry {
+                text: msgid.clone(),
+                translation: msgstr.clone(),
+                context: context.clone(),
+                fuzzy,
+                hash: location.hash,
+                unk: location.unk,
+                source_file: location.source_file,
+                index: location.index,
+                po_file: None,
+                po_line: Some(po_line),
+            });
+        }
+        Ok(warnings)
+    }
 
-                let mut inside_quote = false;
-                #[derive(PartialEq)]
-                enum Escape {
-                    None,
-                    NextEscaped,
-                    SecondEscaped,
-                    WillBeEscaped,
-                    ReadingNumber,
-                }
-                let mut escape = Escape::None;
-                let mut number_being_read = String::new();
-
-                for ch in line.chars() {
-                    if escape == Escape::SecondEscaped {
-                        if ch == 'x' {
-                            escape = Escape::WillBeEscaped;
-                            continue;
-                        } else if ch == 'r' {
-                            escape = Escape::None;
-                            line_no_parentesis.push('\r');
-                        } else {
-                            escape = Escape::None;
-                            line_no_parentesis.push('\\');
-                        };
-                    }
+    /// Split the entries by their source file. The discriminator rules are kept in each part.
+    pub fn split_by_source_file(self) -> BTreeMap<String, GettextWriter> {
+        self.split_by(|source_file| source_file.to_string())
+    }
 
-                    if escape == Escape::NextEscaped {
-                        escape = Escape::None;
-                        match ch {
-                            'n' => line_no_parentesis.push('\n'),
-                            'r' => line_no_parentesis.push('\r'),
-                            '\\' => escape = Escape::SecondEscaped,
-                            ch => line_no_parentesis.push(ch),
-                        }
-                    } else if escape == Escape::WillBeEscaped {
-                        if ch == '{' {
-                            escape = Escape::ReadingNumber;
-                        } else {
-                            todo!("error message");
-                        }
-                    } else if escape == Escape::ReadingNumber {
-                        if ch == '}' {
-                            let new_ch_text = u32::from_str_radix(&number_being_read, 16).unwrap();
-                            let new_ch = char::from_u32(new_ch_text).unwrap();
-                            line_no_parentesis.push(new_ch);
-                            escape = Escape::None;
-                            number_being_read = String::new();
-                        } else {
-                            number_being_read.push(ch);
-                        }
-                    } else if ch == '"' {
-                        inside_quote = !inside_quote;
-                    } else if inside_quote {
-                        if ch == '\\' {
-                            escape = Escape::NextEscaped;
-                        } else if inside_quote {
-                            line_no_parentesis.push(ch);
-                        };
-                    }
-                }
+    /// Split the entries by the group of their source file
+    pub fn split_by_group(self, grouping: &PoGrouping) -> BTreeMap<String, GettextWriter> {
+        self.split_by(|source_file| grouping.group_of(source_file))
+    }
 
-                if escape == Escape::SecondEscaped {
-                    line_no_parentesis.push('\\');
-                }
-            };
+    fn split_by(self, key: impl Fn(&str) -> String) -> BTreeMap<String, GettextWriter> {
+        let mut files: BTreeMap<String, GettextWriter> = BTreeMap::new();
+        let discriminator = self.discriminator;
+        let header = self.header;
+        for entry in self.entries.into_iter() {
+            files
+                .entry(key(&entry.source_file))
+                .or_insert_with(|| GettextWriter {
+                    entries: Vec::new(),
+                    header: header.clone(),
+                    discriminator: discriminator.clone(),
+                })
+                .entries
+                .push(entry);
         }
-        push_current_translation(&mut msgid, &mut msgstr, &mut comment);
+        files
+    }
 
-        (result, warning)
+    /// Return the translations of the entries, with the messages read from the same po entry grouped together
+    pub(crate) fn po_translations(&self) -> Vec<PoTranslation<'_>> {
+        let mut translations: Vec<PoTranslation> = Vec::new();
+        let mut po_entry_index: HashMap<(&Option<PathBuf>, usize), usize> = HashMap::new();
+        for entry in &self.entries {
+            let translation = match &entry.translation {
+                Some(translation) => translation,
+                None => continue,
+            };
+            let message = (entry.source_file.clone(), entry.hash);
+            match entry
+                .po_line
+                .and_then(|po_line| po_entry_index.get(&(&entry.po_file, po_line)))
+            {
+                Some(index) => translations[*index].messages.push(message),
+                None => {
+                    if let Some(po_line) = entry.po_line {
+                        po_entry_index.insert((&entry.po_file, po_line), translations.len());
+                    };
+                    translations.push(PoTranslation {
+                        po_file: &entry.po_file,
+                        po_line: entry.po_line,
+                        translation,
+                        messages: vec![message],
+                    });
+                }
+            }
+        }
+        translations
     }
 
     pub fn merge(&mut self, other: Self) {
+        if self.header.is_none() {
+            self.header = other.header;
+        };
         for entry in other.entries {
             self.entries.push(entry);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FUZZY_PO: &str = concat!(
+        "#: a.bin:0x00000001\n",
+        "#, pmd-unk:a.bin:0x00000001=0\n",
+        "msgid \"Yes\"\n",
+        "msgstr \"Oui\"\n",
+        "\n",
+        "#: a.bin:0x00000002\n",
+        "#, fuzzy, pmd-unk:a.bin:0x00000002=0\n",
+        "msgid \"No\"\n",
+        "msgstr \"Non ?\"\n",
+    );
+
+    fn translations(writer: &GettextWriter) -> Vec<(&str, Option<&str>, bool)> {
+        writer
+            .entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.text.as_str(),
+                    entry.translation.as_deref(),
+                    entry.fuzzy,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fuzzy_translations_are_used() {
+        let (writer, warnings) =
+            GettextWriter::from_po(FUZZY_PO.to_string(), FuzzyPolicy::Use).unwrap();
+        assert_eq!(
+            translations(&writer),
+            vec![("Yes", Some("Oui"), false), ("No", Some("Non ?"), true)]
+        );
+        assert!(matches!(
+            warnings.as_slice(),
+            [PoWarning::FuzzyTranslation(6, msgid)] if msgid == "No"
+        ));
+    }
+
+    #[test]
+    fn fuzzy_translations_fall_back_to_the_source() {
+        let (writer, warnings) =
+            GettextWriter::from_po(FUZZY_PO.to_string(), FuzzyPolicy::Source).unwrap();
+        assert_eq!(
+            translations(&writer),
+            vec![("Yes", Some("Oui"), false), ("No", None, false)]
+        );
+        assert!(matches!(
+            warnings.as_slice(),
+            [PoWarning::FuzzyTranslation(6, msgid)] if msgid == "No"
+        ));
+    }
+
+    #[test]
+    fn fuzzy_translations_are_refused() {
+        let errors = match GettextWriter::from_po(FUZZY_PO.to_string(), FuzzyPolicy::Fail) {
+            Ok(_) => panic!("the fuzzy translation should be refused"),
+            Err(errors) => errors,
+        };
+        assert!(matches!(
+            errors.0.as_slice(),
+            [PoError::FuzzyTranslation(6, 1, msgid)] if msgid == "No"
+        ));
+        assert!(errors.to_string().contains("\"No\""));
+
+        // a fuzzy entry without translation has nothing to refuse
+        let untranslated = FUZZY_PO.replace("\"Non ?\"", "\"\"");
+        assert!(GettextWriter::from_po(untranslated, FuzzyPolicy::Fail).is_ok());
+    }
+
+    #[test]
+    fn streamed_po_file_is_read_identically() {
+        let file = format!(
+            "{}\n#: b.bin:0x00000003\nmsgid \"Back\"\nmsgstr \"Retour\"\n",
+            FUZZY_PO
+        );
+        let (read, warnings) = GettextWriter::from_po(file.clone(), FuzzyPolicy::Source).unwrap();
+        let (streamed, streamed_warnings) = GettextWriter::from_po_reader(
+            // like the standard input, read with ``-``
+            Box::new(io::BufReader::with_capacity(3, file.as_bytes())) as Box<dyn BufRead>,
+            FuzzyPolicy::Source,
+        )
+        .unwrap();
+        assert_eq!(translations(&streamed), translations(&read));
+        let lines = |writer: &GettextWriter| -> Vec<Option<usize>> {
+            writer.entries.iter().map(|entry| entry.po_line).collect()
+        };
+        assert_eq!(lines(&read), vec![Some(4), Some(9), Some(13)]);
+        assert_eq!(lines(&streamed), lines(&read));
+        // the missing pmd-unk flag of the last entry, and the fuzzy translation
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            format!("{:?}", streamed_warnings),
+            format!("{:?}", warnings)
+        );
+
+        let invalid = file.replace("Retour", "\\\\x{zz}");
+        let errors = match GettextWriter::from_po(invalid.clone(), FuzzyPolicy::Fail) {
+            Ok(_) => panic!("the file should be refused"),
+            Err(errors) => errors,
+        };
+        match GettextWriter::from_po_reader(invalid.as_bytes(), FuzzyPolicy::Fail) {
+            Err(PoReadError::Parse(streamed_errors)) => {
+                assert_eq!(streamed_errors.0.len(), 2);
+                assert_eq!(streamed_errors.to_string(), errors.to_string());
+            }
+            _ => panic!("the streamed file should be refused"),
+        };
+    }
+}
@@ -0,0 +1,400 @@
+use crate::{MessageError, Placeholder, PlaceholderTable, PoEntry, DISCRIMINATOR};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// A problem found in the translation of a po entry
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintKind {
+    /// The translation can't be parsed, like with an unescaped ``[`` or a placeholder with an invalid argument
+    InvalidTranslation(MessageError),
+    /// A placeholder of the translation isn't in the code table. Contain the position of its ``[``, its content,
+    /// and the closest name of the code table (as given by [`PlaceholderTable::suggest`]).
+    UnknownPlaceholder(usize, String, Option<String>),
+    /// A placeholder of the source text isn't in the translation
+    MissingPlaceholder(Placeholder),
+    /// A placeholder of the translation isn't in the source text
+    ExtraPlaceholder(Placeholder),
+    /// The positional placeholders are in a different order than in the source text. Contain them in the order
+    /// of the source text, then in the order of the translation.
+    ReorderedPlaceholders(Vec<Placeholder>, Vec<Placeholder>),
+}
+
+fn join_placeholders(placeholders: &[Placeholder]) -> String {
+    placeholders
+        .iter()
+        .map(|placeholder| placeholder.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidTranslation(err) => write!(f, "invalid translation, {}", err),
+            Self::UnknownPlaceholder(position, content, suggestion) => {
+                write!(
+                    f,
+                    "character {}: the placeholder [{}] isn't in the code table",
+                    position, content
+                )?;
+                match suggestion {
+                    // keep the argument of the unknown placeholder, if any
+                    Some(name) => match (name.strip_suffix(':'), content.split_once(':')) {
+                        (Some(name), Some((_, argument))) => {
+                            write!(f, " (did you mean [{}:{}]?)", name, argument)
+                        }
+                        (Some(name), None) => write!(f, " (did you mean [{}:<number>]?)", name),
+                        (None, _) => write!(f, " (did you mean [{}]?)", name),
+                    },
+                    None => Ok(()),
+                }
+            }
+            Self::MissingPlaceholder(placeholder) => write!(
+                f,
+                "the placeholder {} of the source text is missing from the translation",
+                placeholder
+            ),
+            Self::ExtraPlaceholder(placeholder) => write!(
+                f,
+                "the placeholder {} isn't in the source text",
+                placeholder
+            ),
+            Self::ReorderedPlaceholders(source, translation) => write!(
+                f,
+                "the placeholders are in a different order than in the source text: {} became {}",
+                join_placeholders(source),
+                join_placeholders(translation)
+            ),
+        }
+    }
+}
+
+/// A problem found in a po file, with the line it was found at (the one of the ``msgstr``)
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintIssue {
+    pub line: usize,
+    pub kind: LintKind,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+/// Check the placeholders of the translations against the code table and the source texts
+pub struct Linter<'a> {
+    table: &'a PlaceholderTable,
+    /// The names of the placeholders that can be moved freely in a translation, like the name of a character.
+    /// The other ones are positional: they should stay in the same order as in the source text.
+    pub movable: HashSet<String>,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(table: &'a PlaceholderTable) -> Self {
+        Self {
+            table,
+            movable: HashSet::new(),
+        }
+    }
+
+    /// Check the translation of a po entry. The header, obsolete and untranslated entries are ignored, as are
+    /// entries whose source text isn't valid.
+    pub fn lint_po_entry(&self, entry: &PoEntry) -> Vec<LintIssue> {
+        let translation = match entry.translation() {
+            Some(translation) if !entry.is_header() && !entry.obsolete && entry.msgid != " " => {
+                translation
+            }
+            _ => return Vec::new(),
+        };
+        // older files store the context in the msgid
+        let msgid = entry.msgid.split(DISCRIMINATOR).next().unwrap_or("");
+        let source = match self.table.parse(msgid) {
+            Ok(source) => source,
+            Err(_) => return Vec::new(),
+        };
+        let line = entry.msgstr_line(0);
+        let (translated, errors) = match self.table.parse_lenient(translation) {
+            Ok(result) => result,
+            Err(err) => {
+                return vec![LintIssue {
+                    line,
+                    kind: LintKind::InvalidTranslation(err),
+                }]
+            }
+        };
+
+        let mut kinds = Vec::new();
+        for err in errors {
+            kinds.push(match err {
+                MessageError::UnknownPlaceholder(position, content) => {
+                    let suggestion = self.table.suggest(&content).map(str::to_string);
+                    LintKind::UnknownPlaceholder(position, content, suggestion)
+                }
+                err => LintKind::InvalidTranslation(err),
+            });
+        }
+
+        let source_placeholders: Vec<&Placeholder> = source.placeholders().collect();
+        let translated_placeholders: Vec<&Placeholder> = translated.placeholders().collect();
+        let (source_common, missing) = split_common(&source_placeholders, &translated_placeholders);
+        let (translated_common, extra) =
+            split_common(&translated_placeholders, &source_placeholders);
+        kinds.extend(
+            missing
+                .into_iter()
+                .map(|placeholder| LintKind::MissingPlaceholder(placeholder.clone())),
+        );
+        kinds.extend(
+            extra
+                .into_iter()
+                .map(|placeholder| LintKind::ExtraPlaceholder(placeholder.clone())),
+        );
+
+        let positional = |placeholders: Vec<&Placeholder>| -> Vec<Placeholder> {
+            placeholders
+                .into_iter()
+                .filter(|placeholder| !self.movable.contains(&placeholder.name))
+                .cloned()
+                .collect()
+        };
+        let (source_positional, translated_positional) =
+            (positional(source_common), positional(translated_common));
+        if source_positional != translated_positional {
+            kinds.push(LintKind::ReorderedPlaceholders(
+                source_positional,
+                translated_positional,
+            ));
+        };
+
+        kinds
+            .into_iter()
+            .map(|kind| LintIssue { line, kind })
+            .collect()
+    }
+
+    /// Check the translations of the entries of a po file
+    pub fn lint_po_entries<'b>(
+        &self,
+        entries: impl IntoIterator<Item = &'b PoEntry>,
+    ) -> Vec<LintIssue> {
+        entries
+            .into_iter()
+            .flat_map(|entry| self.lint_po_entry(entry))
+            .collect()
+    }
+}
+
+/// Split the placeholders between the ones that are also in ``other`` (counting duplicates) and the ones that
+/// aren't, keeping their order
+fn split_common<'b>(
+    placeholders: &[&'b Placeholder],
+    other: &[&Placeholder],
+) -> (Vec<&'b Placeholder>, Vec<&'b Placeholder>) {
+    let mut available: HashMap<&Placeholder, usize> = HashMap::new();
+    for placeholder in other {
+        *available.entry(placeholder).or_default() += 1;
+    }
+    let mut common = Vec::new();
+    let mut remaining = Vec::new();
+    for placeholder in placeholders {
+        match available.get_mut(placeholder) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                common.push(*placeholder);
+            }
+            _ => remaining.push(*placeholder),
+        }
+    }
+    (common, remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_po;
+
+    fn table() -> PlaceholderTable {
+        PlaceholderTable::from_entries(
+            ["CENTER", "PARTNERNAME", "HERONAME", "VAR:", "K"]
+                .iter()
+                .map(|name| (name.to_string(), 1)),
+        )
+    }
+
+    fn placeholder(name: &str, argument: Option<&str>) -> Placeholder {
+        Placeholder::new(name.to_string(), argument.map(str::to_string))
+    }
+
+    /// Lint a po file containing one entry per ``(source, translation)``
+    fn lint(linter: &Linter, entries: &[(&str, &str)]) -> Vec<LintIssue> {
+        let mut file = String::new();
+        for (source, translation) in entries {
+            file.push_str(&format!(
+                "#: a.bin:0x00000001\nmsgid \"{}\"\nmsgstr \"{}\"\n\n",
+                source, translation
+            ));
+        }
+        let (po_entries, _, errors) = parse_po(&file);
+        assert!(errors.is_empty());
+        linter.lint_po_entries(&po_entries)
+    }
+
+    #[test]
+    fn valid_translations() {
+        let table = table();
+        let linter = Linter::new(&table);
+        assert!(lint(
+            &linter,
+            &[
+                (
+                    "[CENTER]Hello [PARTNERNAME]",
+                    "[CENTER]Bonjour [PARTNERNAME]"
+                ),
+                ("no placeholder", "aucun"),
+                ("[VAR:1] apples", "[VAR:1] pommes"),
+                ("[UNKNOWN] in the source", "[K] ignored"),
+                ("untranslated [CENTER]", ""),
+            ]
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn missing_and_extra_placeholders() {
+        let table = table();
+        let linter = Linter::new(&table);
+        let issues = lint(
+            &linter,
+            &[
+                ("[CENTER]Hello [PARTNERNAME][K]", "Bonjour [PARTNERNAME]"),
+                ("[VAR:1] and [VAR:1]", "[VAR:1] [VAR:2] [HERONAME]"),
+            ],
+        );
+        assert_eq!(
+            issues,
+            vec![
+                LintIssue {
+                    line: 3,
+                    kind: LintKind::MissingPlaceholder(placeholder("CENTER", None)),
+                },
+                LintIssue {
+                    line: 3,
+                    kind: LintKind::MissingPlaceholder(placeholder("K", None)),
+                },
+                LintIssue {
+                    line: 7,
+                    kind: LintKind::MissingPlaceholder(placeholder("VAR", Some("1"))),
+                },
+                LintIssue {
+                    line: 7,
+                    kind: LintKind::ExtraPlaceholder(placeholder("VAR", Some("2"))),
+                },
+                LintIssue {
+                    line: 7,
+                    kind: LintKind::ExtraPlaceholder(placeholder("HERONAME", None)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders() {
+        let table = table();
+        let linter = Linter::new(&table);
+        let issues = lint(
+            &linter,
+            &[(
+                "Hello [PARTNERNAME]",
+                "Bonjour [PARTNERNAM] [VAL:5] [SOMETHING]",
+            )],
+        );
+        assert_eq!(
+            issues
+                .iter()
+                .map(|issue| issue.kind.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                LintKind::UnknownPlaceholder(
+                    8,
+                    "PARTNERNAM".to_string(),
+                    Some("PARTNERNAME".to_string())
+                ),
+                LintKind::UnknownPlaceholder(21, "VAL:5".to_string(), Some("VAR:".to_string())),
+                LintKind::UnknownPlaceholder(29, "SOMETHING".to_string(), None),
+                LintKind::MissingPlaceholder(placeholder("PARTNERNAME", None)),
+            ]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "line 3: character 8: the placeholder [PARTNERNAM] isn't in the code table (did you mean [PARTNERNAME]?)"
+        );
+        assert_eq!(
+            issues[1].kind.to_string(),
+            "character 21: the placeholder [VAL:5] isn't in the code table (did you mean [VAR:5]?)"
+        );
+    }
+
+    #[test]
+    fn invalid_translations() {
+        let table = table();
+        let linter = Linter::new(&table);
+        assert_eq!(
+            lint(&linter, &[("[CENTER]", "[CENTER")]),
+            vec![LintIssue {
+                line: 3,
+                kind: LintKind::InvalidTranslation(MessageError::UnclosedPlaceholder(0)),
+            }]
+        );
+        assert_eq!(
+            lint(&linter, &[("[VAR:1]", "[VAR:x]")])[0].kind,
+            LintKind::InvalidTranslation(MessageError::InvalidArgument(
+                0,
+                "VAR".to_string(),
+                "x".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn reordered_placeholders() {
+        let table = table();
+        let mut linter = Linter::new(&table);
+        let entries = [(
+            "[CENTER][PARTNERNAME] and [HERONAME][K]",
+            "[HERONAME] et [PARTNERNAME][CENTER][K]",
+        )];
+        assert_eq!(
+            lint(&linter, &entries),
+            vec![LintIssue {
+                line: 3,
+                kind: LintKind::ReorderedPlaceholders(
+                    vec![
+                        placeholder("CENTER", None),
+                        placeholder("PARTNERNAME", None),
+                        placeholder("HERONAME", None),
+                        placeholder("K", None),
+                    ],
+                    vec![
+                        placeholder("HERONAME", None),
+                        placeholder("PARTNERNAME", None),
+                        placeholder("CENTER", None),
+                        placeholder("K", None),
+                    ]
+                ),
+            }]
+        );
+
+        // the names can move freely
+        linter.movable.insert("PARTNERNAME".to_string());
+        linter.movable.insert("HERONAME".to_string());
+        assert!(lint(&linter, &entries).is_empty());
+        let issues = lint(&linter, &[("[CENTER] [K]", "[K] [CENTER]")]);
+        assert_eq!(
+            issues[0].kind.to_string(),
+            "the placeholders are in a different order than in the source text: [CENTER] [K] became [K] [CENTER]"
+        );
+    }
+}
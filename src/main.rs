@@ -1,18 +1,24 @@
 use std::{
-    collections::BTreeMap,
-    fs::{create_dir_all, read_dir, File},
-    io::{BufReader, BufWriter, Cursor, Read, Write},
+    collections::{BTreeSet, HashMap},
+    fs::{create_dir_all, read_to_string, write, File},
+    io::{BufReader, Cursor, Read},
     path::{Path, PathBuf},
     str::FromStr,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
-use message_dehash::{get_file_name, try_possible_name};
+use message_dehash::get_file_name;
 use pmd_code_table::CodeTable;
-use pmd_farc::{hash_name, message_dehash, Farc, FarcWriter};
+use pmd_farc::{message_dehash, Farc};
 use pmd_message::MessageBin;
-use translatepmd::{Entry, GettextWriter};
+use translatepmd::{
+    build_farc, build_farc_on_base, build_folder, build_folder_on_base, extract_folder,
+    extract_message_farc, open_message_farc, unnamed_file_name, write_ambiguity_review,
+    AmbiguityCriteria, Catalog, Charset, Discriminator, FuzzyPolicy, GameFormat, GettextWriter,
+    GlyphWidths, Layout, Linter, PlaceholderTable, PoEntry, PoGrouping, PoHeader, PoStorageMode,
+    PoWarning, Project, PROJECT_FILE_NAME,
+};
 
 /// A tool that can be used to translate PSMD (US rom)
 #[derive(Parser)]
@@ -30,114 +36,6 @@ pub fn get_code_table(path: &Path) -> Result<CodeTable> {
     Ok(code_table)
 }
 
-#[derive(Clone, Copy)]
-enum PoStorageMode {
-    File,
-    Folder,
-}
-
-impl FromStr for PoStorageMode {
-    type Err = &'static str;
-
-    fn from_str(s: &str) -> Result<Self, &'static str> {
-        match s {
-            "file" => Ok(PoStorageMode::File),
-            "folder" => Ok(PoStorageMode::Folder),
-            _ => Err("The mod should be either 'file' or 'folder'".into()),
-        }
-    }
-}
-
-impl PoStorageMode {
-    pub fn write(self, path: &Path, content: GettextWriter) -> Result<()> {
-        match self {
-            PoStorageMode::File => {
-                let pot = content.to_pot();
-                let mut file = File::create(path).with_context(|| {
-                    format!("can't create the POT file at {:?}", path.to_string_lossy())
-                })?;
-                file.write_all(pot.as_bytes()).with_context(|| {
-                    format!(
-                        "can't write to the POT file at {:?}",
-                        path.to_string_lossy()
-                    )
-                })?;
-                Ok(())
-            }
-            PoStorageMode::Folder => {
-                let mut files: BTreeMap<String, GettextWriter> = BTreeMap::new();
-                for entry in content.entries.into_iter() {
-                    let source_file = entry.source_file.clone();
-                    let specific_file = files
-                        .entry(source_file)
-                        .or_insert_with(|| GettextWriter::new(Vec::new()));
-                    specific_file.entries.push(entry);
-                }
-                create_dir_all(path).with_context(|| {
-                    format!(
-                        "can't create the POT files folder at {:?}",
-                        path.to_string_lossy()
-                    )
-                })?;
-                for (source_file, gettext) in files.into_iter() {
-                    let source_file = format!("{}.po", source_file.split(".").next().unwrap_or(""));
-                    let mut file =
-                        File::create(path.join(source_file.clone())).with_context(|| {
-                            format!(
-                                "can't create the POT file at {:?}",
-                                path.join(&source_file).to_string_lossy()
-                            )
-                        })?;
-                    file.write_all(gettext.to_pot().as_bytes())
-                        .with_context(|| {
-                            format!(
-                                "can't write to the POT file at {:?}",
-                                path.join(source_file).to_string_lossy()
-                            )
-                        })?;
-                }
-                Ok(())
-            }
-        }
-    }
-
-    pub fn read(self, path: &Path) -> Result<GettextWriter> {
-        match self {
-            Self::File => {
-                let mut input_file = File::open(path)
-                    .with_context(|| format!("can't open the file at {:?}", path))?;
-                let mut po_file = String::new();
-                input_file.read_to_string(&mut po_file)?;
-
-                let (translation, warnings) = GettextWriter::from_po(po_file);
-                for warning in &warnings {
-                    println!("non fatal warning: {}", warning);
-                }
-                return Ok(translation);
-            }
-            Self::Folder => {
-                let mut gettext = GettextWriter::new(Vec::new());
-                for entry in read_dir(path).with_context(|| {
-                    format!("can't get the list of files in the folder {:?}", path)
-                })? {
-                    let entry = entry.with_context(|| {
-                        format!("Can't read an entry of a subfile of {:?}", path)
-                    })?;
-                    let subfile = (Self::File {}).read(&entry.path()).with_context(|| {
-                        format!(
-                            "Can't read the {:?} subfile of {:?}",
-                            entry.file_name(),
-                            path
-                        )
-                    })?;
-                    gettext.merge(subfile);
-                }
-                Ok(gettext)
-            }
-        }
-    }
-}
-
 enum Mode {
     Farc,
     Folder,
@@ -155,11 +53,192 @@ impl FromStr for Mode {
     }
 }
 
+impl From<GameFormat> for Mode {
+    fn from(format: GameFormat) -> Self {
+        match format {
+            GameFormat::Farc => Mode::Farc,
+            GameFormat::Folder => Mode::Folder,
+        }
+    }
+}
+
 #[derive(Parser)]
 enum SubCommand {
     ToPot(ToPotParameter),
     FromPo(FromPoParameter),
     ConvertTranslation(ConvertTranslationParameter),
+    Update(UpdateParameter),
+    Verify(VerifyParameter),
+    Lint(LintParameter),
+    CheckLayout(CheckLayoutParameter),
+    Extract(ProjectParameter),
+    Build(ProjectParameter),
+}
+
+/// Use the pmdtranslate.toml project file. "extract" write the template and update (or create) the translations
+/// with it, "build" build the message files of the translations.
+#[derive(Parser)]
+struct ProjectParameter {
+    /// The project file
+    #[clap(long, default_value = PROJECT_FILE_NAME)]
+    project: PathBuf,
+    /// Only process the translation with this language code
+    #[clap(long)]
+    language: Option<String>,
+}
+
+/// Check that extracting a message farc and rebuilding it without translation give back the same files
+#[derive(Parser)]
+struct VerifyParameter {
+    /// The code_table.bin file, containing information about placeholder
+    code_table: PathBuf,
+    /// The message farc file to check (like message_us.bin), with its .lst file next to it
+    input: PathBuf,
+}
+
+/// Check the placeholders of the translations with the code table: invalid or unknown placeholders, and
+/// placeholders that are missing, extra or in a different order than in the source text
+#[derive(Parser)]
+struct LintParameter {
+    /// The code_table.bin file, containing information about placeholder
+    code_table: PathBuf,
+    /// The type of the translation. either file or folder
+    input_type: PoStorageMode,
+    /// The translated po file/folder. "-" read it from the standard input.
+    input: PathBuf,
+    /// A placeholder (like "hero", without the brackets and argument) that can be moved freely in the
+    /// translation. The order of the other placeholders is checked. Can be repeated.
+    #[clap(long = "movable", value_name = "NAME", number_of_values = 1)]
+    movable: Vec<String>,
+}
+
+/// Simulate the layout of the translations in the dialogue box, and report the lines that are too wide and the
+/// boxes with too many lines
+#[derive(Parser)]
+struct CheckLayoutParameter {
+    /// The code_table.bin file, containing information about placeholder
+    code_table: PathBuf,
+    /// The type of the translation. either file or folder
+    input_type: PoStorageMode,
+    /// The translated po file/folder. "-" read it from the standard input.
+    input: PathBuf,
+    /// A file containing the width of the glyphs, with one "<width> <characters>" line per width (like "6 abcde" or
+    /// "3 U+0020 il"), and "default <width>" for the other characters. Without it, the widths are counted in
+    /// characters.
+    #[clap(long)]
+    glyph_widths: Option<PathBuf>,
+    /// The maximal width of a line
+    #[clap(long)]
+    max_width: Option<u32>,
+    /// The maximal number of lines of a box
+    #[clap(long)]
+    max_lines: Option<usize>,
+    /// Don't wrap the lines that are too wide at spaces: every line must fit as written
+    #[clap(long)]
+    no_wrap: bool,
+    /// A placeholder that start a new box (like "K", without the brackets). Can be repeated.
+    #[clap(long = "page-break", value_name = "NAME", number_of_values = 1)]
+    page_breaks: Vec<String>,
+    /// The longest text a placeholder can be replaced by, like "PARTNERNAME=Mmmmmmmmmm". Can be repeated. The
+    /// other placeholders take no space.
+    #[clap(long = "placeholder", value_name = "NAME=TEXT", number_of_values = 1)]
+    placeholders: Vec<String>,
+}
+
+/// How the messages are split in the po files of a folder. By default, there is one po file per message file.
+#[derive(Parser)]
+struct GroupingParameter {
+    /// Put the message files matching PATTERN in the GROUP po file of the folder, like "script_m01*=chapter_1"
+    /// (a pattern ending with * match every file starting with it). Can be repeated, the first matching rule is used.
+    #[clap(long = "group", value_name = "PATTERN=GROUP", number_of_values = 1)]
+    groups: Vec<String>,
+    /// A file containing one "PATTERN = GROUP" rule per line, used after the --group rules
+    #[clap(long)]
+    grouping_file: Option<PathBuf>,
+    /// Split the po files of the folder in numbered parts of at most this number of entries
+    #[clap(long)]
+    max_entries: Option<usize>,
+    /// Remove the po files of the folder that aren't part of the new grouping, like the ones of an earlier grouping
+    #[clap(long)]
+    prune: bool,
+}
+
+impl GroupingParameter {
+    fn grouping(&self) -> Result<PoGrouping> {
+        let mut grouping = PoGrouping::default();
+        grouping.max_entries = self.max_entries;
+        grouping.prune = self.prune;
+        for rule in &self.groups {
+            let (pattern, group) = match PoGrouping::parse_rule(rule) {
+                Some(rule) => rule,
+                None => bail!("the group {:?} should be of the form PATTERN=GROUP", rule),
+            };
+            grouping.add_rule(pattern, group);
+        }
+        if let Some(grouping_file) = &self.grouping_file {
+            grouping
+                .add_rules_file(&read_to_string(grouping_file).with_context(|| {
+                    format!("can't read the grouping file at {:?}", grouping_file)
+                })?)
+                .with_context(|| format!("can't parse the grouping file at {:?}", grouping_file))?;
+        };
+        Ok(grouping)
+    }
+}
+
+/// Detect the strings shared by several messages that may need a different translation in each place.
+/// A shared string matching any of the criteria is ambiguous.
+#[derive(Parser)]
+struct AmbiguityParameter {
+    /// Criterion: strings of at most this number of characters
+    #[clap(long)]
+    ambiguous_max_length: Option<usize>,
+    /// Criterion: strings used in at least this number of message files
+    #[clap(long)]
+    ambiguous_min_files: Option<usize>,
+    /// Criterion: strings made of a single word
+    #[clap(long)]
+    ambiguous_single_word: bool,
+    /// Write the ambiguous strings to this file, as discriminator rules to review. Once reviewed, give it to
+    /// --discriminator-file.
+    #[clap(long)]
+    ambiguity_review: Option<PathBuf>,
+    /// Translate the ambiguous strings separately at each location, without review
+    #[clap(long)]
+    split_ambiguous: bool,
+}
+
+impl AmbiguityParameter {
+    fn criteria(&self) -> AmbiguityCriteria {
+        AmbiguityCriteria {
+            max_length: self.ambiguous_max_length,
+            min_source_files: self.ambiguous_min_files,
+            single_word: self.ambiguous_single_word,
+        }
+    }
+}
+
+/// Merge existing translations into a newly extracted POT file (like msgmerge)
+#[derive(Parser)]
+struct UpdateParameter {
+    /// The type of the existing translation. either file or folder
+    existing_type: PoStorageMode,
+    /// The existing translated po file/folder. "-" read it from the standard input.
+    existing: PathBuf,
+    /// The type of the new template. either file or folder
+    template_type: PoStorageMode,
+    /// The newly extracted pot file/folder
+    template: PathBuf,
+    /// The type of output. either file or folder
+    output_type: PoStorageMode,
+    /// The output po file/folder. It can be the same as the existing translation. "-" write it to the standard
+    /// output.
+    output: PathBuf,
+    /// Don't reuse the translation of similar strings (as fuzzy translation). It can be slow on big files.
+    #[clap(long)]
+    no_fuzzy_matching: bool,
+    #[clap(flatten)]
+    grouping: GroupingParameter,
 }
 
 #[derive(Parser)]
@@ -168,6 +247,8 @@ struct ConvertTranslationParameter {
     input: PathBuf,
     output_type: PoStorageMode,
     output: PathBuf,
+    #[clap(flatten)]
+    grouping: GroupingParameter,
 }
 
 #[derive(Parser)]
@@ -180,10 +261,28 @@ struct ToPotParameter {
     storage_mode: PoStorageMode,
     /// The input message folder/farc file (depend on mode)
     input: PathBuf,
-    /// The output pot file/folder
+    /// The output pot file/folder. "-" write the pot file to the standard output.
     output: PathBuf,
     /// The list of phrase that could have multiple different meaning
     unique: Vec<String>,
+    /// A file of discriminator rules, deciding which strings are translated separately at each location
+    /// (one rule per line, like "regex ^Yes$", "file script_m01*", "hash menu.bin:0x0000012C", "contains yes",
+    /// or one of them preceded by "!" for an exception). They are used after the phrases given as argument.
+    #[clap(long)]
+    discriminator_file: Option<PathBuf>,
+    /// Print the strings translated separately, with the rule that split them
+    #[clap(long)]
+    discrimination_report: bool,
+    #[clap(flatten)]
+    ambiguity: AmbiguityParameter,
+    /// The Project-Id-Version of the pot header. Default to the game and the region of the input (like "PSMD US")
+    #[clap(long)]
+    project_id_version: Option<String>,
+    /// The Language of the pot header, like "fr"
+    #[clap(long, default_value = "")]
+    language: String,
+    #[clap(flatten)]
+    grouping: GroupingParameter,
 }
 
 #[derive(Parser)]
@@ -192,10 +291,24 @@ struct FromPoParameter {
     mode: Mode,
     /// The code_table.bin file, containing information about placeholder
     code_table: PathBuf,
-    /// The type of output. either file or folder
+    /// The type of input. either file or folder
     storage_mode: PoStorageMode,
+    /// The input po file/folder. "-" read the po file from the standard input.
     input: PathBuf,
+    /// The output message farc file/folder (depend on mode)
     output: PathBuf,
+    /// The original message farc file/folder (depend on mode). Files and messages that are not in the po input
+    /// are kept from it, rather than being removed.
+    #[clap(long)]
+    base: Option<PathBuf>,
+    /// What to do with translation marked as fuzzy. Either use (use them as normal translation),
+    /// source (use the untranslated text instead) or fail (refuse to build the file)
+    #[clap(long, default_value = "use")]
+    fuzzy: FuzzyPolicy,
+    /// A file containing every character the game can display (line breaks are ignored). Translations using
+    /// other characters are refused.
+    #[clap(long)]
+    charset: Option<PathBuf>,
 }
 
 fn main() -> Result<()> {
@@ -209,95 +322,189 @@ fn main() -> Result<()> {
         SubCommand::ConvertTranslation(convert_p) => {
             convert_translation(&convert_p).context("While converting the translation")?
         }
+        SubCommand::Update(update_p) => {
+            update(&update_p).context("While updating the translation")?
+        }
+        SubCommand::Verify(verify_p) => verify(&verify_p).context("While verifying the farc")?,
+        SubCommand::Lint(lint_p) => lint(&lint_p).context("While checking the translation")?,
+        SubCommand::CheckLayout(layout_p) => {
+            check_layout(&layout_p).context("While checking the layout of the translation")?
+        }
+        SubCommand::Extract(project_p) => {
+            extract(&project_p).context("While extracting the project")?
+        }
+        SubCommand::Build(project_p) => build(&project_p).context("While building the project")?,
     };
 
     Ok(())
 }
 
-fn topot(topot_p: &ToPotParameter) -> Result<()> {
-    let code_table =
-        get_code_table(&topot_p.code_table).context("can't read the code_table.bin file")?;
+/// Open a message farc file, and find the name of its files with the adjacent list file
+fn open_farc_file(path: &Path) -> Result<Farc<BufReader<File>>> {
+    let farc_file = BufReader::new(
+        File::open(path).with_context(|| format!("can't open the farc file at {:?}", path))?,
+    );
+    let list_file_name = get_file_name(
+        path.file_name()
+            .context("the farc path doesn't have a file name")?
+            .to_str()
+            .context("the input file name isn't a valid utf8 file")?,
+    )
+    .context("can't get the associated list file")?;
 
-    let mut gettext = GettextWriter::new(topot_p.unique.clone());
-    let code_to_text = code_table.generate_code_to_text();
-    match topot_p.mode {
-        Mode::Folder => {
-            for file_maybe in
-                read_dir(&topot_p.input).context("can't list files in the input directory")?
-            {
-                let file_entry = file_maybe
-                    .context("can't get data about one element of the input directory")?;
-                let file_name = file_entry.file_name();
-                if file_name == "name_sort.bin" {
-                    continue;
-                };
-                let file_name_str = file_name
-                    .to_str()
-                    .context("can't transform the file name to an utf8 string")?;
-                let file_path = topot_p.input.join(file_entry.file_name());
-                let mut file = BufReader::new(File::open(file_path)?);
-                let message_bin = MessageBin::load_file(&mut file, Some(&code_to_text))?;
-                for (hash, unk, text) in message_bin.messages().iter() {
-                    gettext.entries.push(Entry::new(
-                        text.clone(),
-                        *hash,
-                        *unk,
-                        file_name_str.to_string(),
-                    ));
-                }
-            }
-        }
-        Mode::Farc => {
-            let farc_file = BufReader::new(
-                File::open(&topot_p.input).context("can't open the input farc file")?,
+    let list_file_path = &path
+        .parent()
+        .map(|x| x.to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(&list_file_name);
+
+    let list_file = BufReader::new(
+        File::open(list_file_path)
+            .with_context(|| format!("can't open the related list file {:?}", list_file_path))?,
+    );
+
+    open_message_farc(farc_file, list_file).with_context(|| {
+        format!(
+            "can't read the farc at {:?} with its list file {:?}",
+            path, list_file_path
+        )
+    })
+}
+
+/// Read a translation, printing the warnings. Also return the number of fuzzy translations.
+fn read_translation(
+    storage_mode: PoStorageMode,
+    path: &Path,
+    fuzzy_policy: FuzzyPolicy,
+) -> Result<(Catalog, usize)> {
+    let (catalog, warnings) = storage_mode.read(path, fuzzy_policy)?;
+    let mut fuzzy_count = 0;
+    for warning in &warnings {
+        if let PoWarning::FuzzyTranslation(_, _) = warning {
+            fuzzy_count += 1;
+        };
+        eprintln!("non fatal warning: {}", warning);
+    }
+    Ok((catalog, fuzzy_count))
+}
+
+/// Print the po files of a written folder that aren't part of its grouping, that were removed with ``--prune``
+fn print_stale_po_files(stale: &[PathBuf], grouping: &PoGrouping) {
+    for path in stale {
+        if grouping.prune {
+            eprintln!("removed {:?}", path);
+        } else {
+            eprintln!(
+                "warning: the po file {:?} isn't part of the new grouping, but will still be read with the folder. Remove it, or use --prune",
+                path
             );
-            let mut farc = Farc::new(farc_file)?;
-            let list_file_name = get_file_name(
-                topot_p
-                    .input
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .context("the input file name isn't a valid utf8 file")?,
-            )
-            .context("can't get the associated list file")?;
+        };
+    }
+}
+
+/// Read the po entries, keeping all their metadata, and printing the warnings
+fn read_po_entries(storage_mode: PoStorageMode, path: &Path) -> Result<Vec<PoEntry>> {
+    let (entries, warnings) = storage_mode.read_po_entries(path)?;
+    for warning in &warnings {
+        eprintln!("non fatal warning: {}", warning);
+    }
+    Ok(entries)
+}
 
-            let list_file_path = &topot_p
-                .input
-                .parent()
-                .map(|x| x.to_path_buf())
-                .unwrap_or(PathBuf::from("."))
-                .join(&list_file_name);
+/// The name of the game, followed by the region of the message farc/folder (``message_us`` is ``PSMD US``)
+fn default_project_id_version(input: &Path) -> String {
+    match input
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.strip_prefix("message_"))
+    {
+        Some(region) => format!("PSMD {}", region.to_uppercase()),
+        None => "PSMD".to_string(),
+    }
+}
 
-            let mut list_file = BufReader::new(File::open(list_file_path).with_context(|| {
-                format!("can't open the related list file {:?}", list_file_path)
-            })?);
+fn topot(topot_p: &ToPotParameter) -> Result<()> {
+    let code_table =
+        get_code_table(&topot_p.code_table).context("can't read the code_table.bin file")?;
 
-            try_possible_name(&mut farc, &mut list_file).with_context(|| {
-                format!("error reading the related list file {:?}", list_file_path)
+    let mut catalog = match topot_p.mode {
+        Mode::Folder => extract_folder(&topot_p.input, &code_table)?,
+        Mode::Farc => {
+            let farc = open_farc_file(&topot_p.input)?;
+            let unknown_name_count = farc.file_unknown_name();
+            if unknown_name_count > 0 {
+                eprintln!(
+                    "warning: the name of {} files of the farc are unknown. They are extracted as hash_0x<hash of the name>",
+                    unknown_name_count
+                );
+            };
+            extract_message_farc(&farc, &code_table)?
+        }
+    };
+    let mut discriminator = Discriminator::from_substrings(topot_p.unique.clone());
+    if let Some(discriminator_file) = &topot_p.discriminator_file {
+        discriminator
+            .add_rules_file(&read_to_string(discriminator_file).with_context(|| {
+                format!(
+                    "can't read the discriminator file at {:?}",
+                    discriminator_file
+                )
+            })?)
+            .with_context(|| {
+                format!(
+                    "can't parse the discriminator file at {:?}",
+                    discriminator_file
+                )
             })?;
+    };
+    catalog.set_discriminator(discriminator.clone());
 
-            for file_name in farc.iter_name() {
-                let mut message_file = farc.get_named_file(file_name).with_context(|| {
-                    format!("can't load the {:?} message file from the farc", file_name)
-                })?;
-                let message_bin = MessageBin::load_file(&mut message_file, Some(&code_to_text))?;
-                for (hash, unk, text) in message_bin.messages().iter() {
-                    gettext.entries.push(Entry::new(
-                        text.clone(),
-                        *hash,
-                        *unk,
-                        file_name.to_string(),
-                    ));
-                }
+    let criteria = topot_p.ambiguity.criteria();
+    if criteria.is_empty() {
+        if topot_p.ambiguity.ambiguity_review.is_some() || topot_p.ambiguity.split_ambiguous {
+            bail!("give at least one ambiguity criterion (like --ambiguous-single-word)");
+        };
+    } else {
+        let ambiguous = catalog.find_ambiguous(&criteria);
+        eprintln!("{} shared strings may be ambiguous", ambiguous.len());
+        if let Some(review_path) = &topot_p.ambiguity.ambiguity_review {
+            write(review_path, write_ambiguity_review(&ambiguous)).with_context(|| {
+                format!("can't write the ambiguity review list at {:?}", review_path)
+            })?;
+        };
+        if topot_p.ambiguity.split_ambiguous {
+            // the rules given by the user, like the reviewed exceptions, take priority
+            for string in &ambiguous {
+                discriminator.add_fallback_rule(string.to_rule());
             }
+            catalog.set_discriminator(discriminator);
+        };
+    };
+
+    if topot_p.discrimination_report {
+        let report = catalog.discrimination_report();
+        for (entry, rule) in &report {
+            eprintln!(
+                "{}:0x{:08X} {:?} is translated separately ({})",
+                entry.source_file, entry.hash, entry.text, rule
+            );
         }
-    }
+        eprintln!("{} strings are translated separately", report.len());
+    };
+    catalog.header = Some(PoHeader::new_template(
+        topot_p
+            .project_id_version
+            .clone()
+            .unwrap_or_else(|| default_project_id_version(&topot_p.input)),
+        topot_p.language.clone(),
+    ));
 
-    topot_p
+    let grouping = topot_p.grouping.grouping()?;
+    let stale = topot_p
         .storage_mode
-        .write(&topot_p.output, gettext)
+        .write(&topot_p.output, catalog, &grouping)
         .context("can't write the result file")?;
+    print_stale_po_files(&stale, &grouping);
 
     Ok(())
 }
@@ -305,46 +512,397 @@ fn topot(topot_p: &ToPotParameter) -> Result<()> {
 fn frompo(frompo_p: &FromPoParameter) -> Result<()> {
     let code_table = get_code_table(&frompo_p.code_table)?;
 
-    let text_to_code = code_table.generate_text_to_code();
-    let translation = frompo_p
-        .storage_mode
-        .read(&frompo_p.input)
-        .context("can't read the po input")?;
+    let (translation, fuzzy_count) =
+        read_translation(frompo_p.storage_mode, &frompo_p.input, frompo_p.fuzzy)
+            .context("can't read the po input")?;
+
+    match frompo_p.fuzzy {
+        FuzzyPolicy::Use => println!("{} fuzzy translations were used", fuzzy_count),
+        FuzzyPolicy::Source => println!(
+            "{} fuzzy translations were replaced by the source text",
+            fuzzy_count
+        ),
+        // the fuzzy translations were already refused while reading
+        FuzzyPolicy::Fail => (),
+    };
+
+    // check every translation before writing anything, as the build stop at the first one it can't encode
+    let mut charset = Charset::new(&code_table);
+    if let Some(charset_path) = &frompo_p.charset {
+        charset.add_charset_file(
+            &read_to_string(charset_path)
+                .with_context(|| format!("can't read the charset file at {:?}", charset_path))?,
+        );
+    };
+    let issues = translation.check_encoding(&charset, &PlaceholderTable::new(&code_table));
+    if !issues.is_empty() {
+        for issue in &issues {
+            eprintln!("{}", issue);
+        }
+        bail!(
+            "{} problems prevent the translation from being encoded (see above). Nothing was written.",
+            issues.len()
+        );
+    };
 
     match frompo_p.mode {
-        Mode::Folder => todo!(),
-        Mode::Farc => {
-            let mut translated_file: BTreeMap<String, MessageBin> = BTreeMap::new();
-            for entry in translation.entries.iter() {
-                let message_bin = if let Some(entry) = translated_file.get_mut(&entry.source_file) {
-                    entry
-                } else {
-                    translated_file.insert(entry.source_file.clone(), MessageBin::default());
-                    translated_file.get_mut(&entry.source_file).unwrap()
-                };
-                message_bin.insert(entry.hash, entry.unk, entry.text.clone());
-            }
-            let mut farc_writer = FarcWriter::default();
-            for (file_name, message_bin) in translated_file {
-                let mut buffer = Cursor::new(Vec::new());
-                message_bin.write(&mut buffer, Some(&text_to_code))?;
-                farc_writer.add_hashed_file(hash_name(&file_name), buffer.into_inner());
+        Mode::Folder => match &frompo_p.base {
+            Some(base) => {
+                let kept_files =
+                    build_folder_on_base(&translation, &code_table, base, &frompo_p.output)?;
+                println!(
+                    "{} untranslated files were kept from the base folder",
+                    kept_files
+                );
             }
-            let mut out_file = BufWriter::new(File::create(&frompo_p.output)?);
-            farc_writer.write_hashed(&mut out_file)?;
+            None => build_folder(&translation, &code_table, &frompo_p.output)?,
+        },
+        Mode::Farc => {
+            // the farc is built in memory, as the base may be the output file
+            let mut farc_content = Cursor::new(Vec::new());
+            match &frompo_p.base {
+                Some(base) => {
+                    let farc = open_farc_file(base).context("can't open the base farc file")?;
+                    let kept_files =
+                        build_farc_on_base(&translation, &code_table, &farc, &mut farc_content)?;
+                    println!(
+                        "{} untranslated files were kept from the base farc",
+                        kept_files
+                    );
+                }
+                None => build_farc(&translation, &code_table, &mut farc_content)?,
+            };
+            write(&frompo_p.output, farc_content.into_inner())
+                .with_context(|| format!("can't write the farc file at {:?}", frompo_p.output))?;
         }
     }
     Ok(())
 }
 
 fn convert_translation(converttrans_p: &ConvertTranslationParameter) -> Result<()> {
-    let gettext_input = converttrans_p
-        .input_type
-        .read(&converttrans_p.input)
+    let entries = read_po_entries(converttrans_p.input_type, &converttrans_p.input)
         .context("can't read the input file")?;
-    converttrans_p
+    let grouping = converttrans_p.grouping.grouping()?;
+    let stale = converttrans_p
         .output_type
-        .write(&converttrans_p.output, gettext_input)
+        .write_po_entries(&converttrans_p.output, entries, &grouping)
         .context("can't write the output file")?;
+    print_stale_po_files(&stale, &grouping);
+    Ok(())
+}
+
+fn update(update_p: &UpdateParameter) -> Result<()> {
+    let existing = read_po_entries(update_p.existing_type, &update_p.existing)
+        .context("can't read the existing translation")?;
+    let template = read_po_entries(update_p.template_type, &update_p.template)
+        .context("can't read the new template")?;
+    let (result, report) = GettextWriter::update(existing, template, !update_p.no_fuzzy_matching);
+    eprintln!(
+        "{} translations kept, {} fuzzy translations for changed strings, {} fuzzy translations from similar strings, {} untranslated entries, {} obsolete translations",
+        report.kept, report.changed_source, report.similar, report.untranslated, report.obsolete
+    );
+    let grouping = update_p.grouping.grouping()?;
+    let stale = update_p
+        .output_type
+        .write_po_entries(&update_p.output, result, &grouping)
+        .context("can't write the updated translation")?;
+    print_stale_po_files(&stale, &grouping);
+    Ok(())
+}
+
+fn lint(lint_p: &LintParameter) -> Result<()> {
+    let code_table =
+        get_code_table(&lint_p.code_table).context("can't read the code_table.bin file")?;
+    let table = PlaceholderTable::new(&code_table);
+    let mut linter = Linter::new(&table);
+    linter.movable = lint_p.movable.iter().cloned().collect();
+    let (files, warnings) = lint_p
+        .input_type
+        .read_po_files(&lint_p.input)
+        .context("can't read the translation")?;
+    for warning in &warnings {
+        eprintln!("non fatal warning: {}", warning);
+    }
+
+    let mut issue_count = 0;
+    for (path, entries) in &files {
+        for issue in linter.lint_po_entries(entries) {
+            issue_count += 1;
+            println!("{}:{}: {}", path.display(), issue.line, issue.kind);
+        }
+    }
+    if issue_count > 0 {
+        bail!("{} problems were found in the translation", issue_count);
+    };
+    Ok(())
+}
+
+fn check_layout(layout_p: &CheckLayoutParameter) -> Result<()> {
+    if layout_p.max_width.is_none() && layout_p.max_lines.is_none() {
+        bail!("nothing to check: give the limits with --max-width and/or --max-lines");
+    };
+    let code_table =
+        get_code_table(&layout_p.code_table).context("can't read the code_table.bin file")?;
+    let glyph_widths = match &layout_p.glyph_widths {
+        Some(path) => GlyphWidths::parse(
+            &read_to_string(path)
+                .with_context(|| format!("can't read the glyph width file at {:?}", path))?,
+        )
+        .with_context(|| format!("can't parse the glyph width file at {:?}", path))?,
+        None => GlyphWidths::monospace(1),
+    };
+    let mut layout = Layout::new(glyph_widths);
+    layout.max_width = layout_p.max_width;
+    layout.max_lines = layout_p.max_lines;
+    layout.wrap = !layout_p.no_wrap;
+    layout.page_breaks = layout_p.page_breaks.iter().cloned().collect();
+    for rule in &layout_p.placeholders {
+        if !layout.add_placeholder_text(rule) {
+            bail!("the placeholder {:?} should be of the form NAME=TEXT", rule);
+        };
+    }
+
+    let (translation, _) = read_translation(layout_p.input_type, &layout_p.input, FuzzyPolicy::Use)
+        .context("can't read the translation")?;
+    let issues = translation.check_layout(&layout, &PlaceholderTable::new(&code_table));
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if !issues.is_empty() {
+        bail!(
+            "{} layout problems were found in the translation",
+            issues.len()
+        );
+    };
+    Ok(())
+}
+
+fn verify(verify_p: &VerifyParameter) -> Result<()> {
+    let code_table =
+        get_code_table(&verify_p.code_table).context("can't read the code_table.bin file")?;
+    let code_to_text = code_table.generate_code_to_text();
+    let farc = open_farc_file(&verify_p.input)?;
+
+    let extracted =
+        extract_message_farc(&farc, &code_table).context("can't extract the messages")?;
+    let (reparsed, _) = GettextWriter::from_po(extracted.to_pot(), FuzzyPolicy::Use)
+        .context("can't read back the generated pot file")?;
+
+    let mut difference_count = 0;
+
+    // check that every text survived the escaping of the pot file
+    let reparsed_text: HashMap<(&str, u32), &str> = reparsed
+        .entries
+        .iter()
+        .map(|entry| {
+            (
+                (entry.source_file.as_str(), entry.hash),
+                entry.text.as_str(),
+            )
+        })
+        .collect();
+    for entry in &extracted.entries {
+        match reparsed_text.get(&(entry.source_file.as_str(), entry.hash)) {
+            Some(text) if *text == entry.text => (),
+            Some(text) => {
+                difference_count += 1;
+                println!(
+                    "the message {} of {:?} changed while going through the pot file: {:?} became {:?}",
+                    entry.hash, entry.source_file, entry.text, text
+                );
+            }
+            None => {
+                difference_count += 1;
+                println!(
+                    "the message {} of {:?} is missing from the pot file",
+                    entry.hash, entry.source_file
+                );
+            }
+        }
+    }
+
+    // rebuild the farc, and compare its files with the original ones
+    let mut rebuilt_file = Cursor::new(Vec::new());
+    build_farc(&reparsed, &code_table, &mut rebuilt_file).context("can't rebuild the farc")?;
+    let rebuilt_bytes = rebuilt_file.into_inner();
+    let rebuilt = Farc::new(Cursor::new(rebuilt_bytes.clone()))
+        .context("can't read back the rebuilt farc")?;
+
+    for (hash, file_name) in farc.iter() {
+        let file_name = file_name
+            .cloned()
+            .unwrap_or_else(|| unnamed_file_name(hash));
+        let mut original = Vec::new();
+        farc.get_hashed_file(hash)?
+            .read_to_end(&mut original)
+            .with_context(|| format!("can't read the {:?} file of the farc", file_name))?;
+        let mut rebuilt_message = Vec::new();
+        match rebuilt.get_hashed_file(hash) {
+            Ok(mut file) => file.read_to_end(&mut rebuilt_message)?,
+            Err(_) => {
+                difference_count += 1;
+                println!("the file {:?} is missing from the rebuilt farc", file_name);
+                continue;
+            }
+        };
+        if original == rebuilt_message {
+            continue;
+        };
+
+        difference_count += 1;
+        let original = MessageBin::load_file(&mut Cursor::new(original), Some(&code_to_text))?;
+        let rebuilt_message =
+            MessageBin::load_file(&mut Cursor::new(rebuilt_message), Some(&code_to_text))?;
+        let first_difference = original
+            .messages()
+            .iter()
+            .zip(rebuilt_message.messages().iter())
+            .find(|(original, rebuilt)| original != rebuilt);
+        match first_difference {
+            Some(((hash, unk, text), (rebuilt_hash, rebuilt_unk, rebuilt_text))) => println!(
+                "the file {:?} differ. Its first different message is hash {} unk {} {:?}, rebuilt as hash {} unk {} {:?}",
+                file_name, hash, unk, text, rebuilt_hash, rebuilt_unk, rebuilt_text
+            ),
+            None if original.messages().len() != rebuilt_message.messages().len() => println!(
+                "the file {:?} differ. It contain {} messages, but {} once rebuilt",
+                file_name,
+                original.messages().len(),
+                rebuilt_message.messages().len()
+            ),
+            None => println!(
+                "the file {:?} differ, but contain the same messages",
+                file_name
+            ),
+        };
+    }
+    let original_hashes: BTreeSet<u32> = farc.iter_all_hash().copied().collect();
+    for hash in rebuilt.iter_all_hash() {
+        if !original_hashes.contains(hash) {
+            difference_count += 1;
+            println!(
+                "the rebuilt farc contain the file {:?}, which isn't in the original",
+                unnamed_file_name(*hash)
+            );
+        }
+    }
+
+    if difference_count > 0 {
+        bail!(
+            "{} differences were found between the original and the rebuilt farc",
+            difference_count
+        );
+    };
+    println!(
+        "the {} files are identical after extraction and rebuild",
+        farc.file_count()
+    );
+    let mut original_bytes = Vec::new();
+    File::open(&verify_p.input)?.read_to_end(&mut original_bytes)?;
+    if original_bytes != rebuilt_bytes {
+        println!("note: the farc container itself differ (the order or alignment of the files)");
+    };
+    Ok(())
+}
+
+fn project_grouping(project: &Project) -> GroupingParameter {
+    GroupingParameter {
+        groups: project.po.groups.clone(),
+        grouping_file: project.po.grouping_file.clone(),
+        max_entries: project.po.max_entries,
+        prune: project.po.prune,
+    }
+}
+
+fn extract(project_p: &ProjectParameter) -> Result<()> {
+    let project = Project::load(&project_p.project)?;
+    let languages = project.select_languages(project_p.language.as_deref())?;
+    let storage_mode = project.po.storage;
+
+    eprintln!("extracting the template to {:?}", project.po.template);
+    topot(&ToPotParameter {
+        mode: project.game.format.into(),
+        code_table: project.game.code_table.clone(),
+        storage_mode,
+        input: project.game.messages.clone(),
+        output: project.po.template.clone(),
+        unique: project.po.discriminators.clone(),
+        discriminator_file: project.po.discriminator_file.clone(),
+        discrimination_report: false,
+        ambiguity: AmbiguityParameter {
+            ambiguous_max_length: project.po.ambiguity.max_length,
+            ambiguous_min_files: project.po.ambiguity.min_source_files,
+            ambiguous_single_word: project.po.ambiguity.single_word,
+            ambiguity_review: project.po.ambiguity_review.clone(),
+            split_ambiguous: project.po.split_ambiguous,
+        },
+        project_id_version: project.game.project_id_version.clone(),
+        language: String::new(),
+        grouping: project_grouping(&project),
+    })
+    .context("can't extract the template")?;
+
+    for language in languages {
+        if language.po.exists() {
+            eprintln!("updating the {} translation", language.code);
+            update(&UpdateParameter {
+                existing_type: storage_mode,
+                existing: language.po.clone(),
+                template_type: storage_mode,
+                template: project.po.template.clone(),
+                output_type: storage_mode,
+                output: language.po.clone(),
+                no_fuzzy_matching: false,
+                grouping: project_grouping(&project),
+            })
+            .with_context(|| format!("can't update the {} translation", language.code))?;
+        } else {
+            eprintln!("creating the {} translation", language.code);
+            let (mut translation, _) =
+                read_translation(storage_mode, &project.po.template, FuzzyPolicy::Use)?;
+            if let Some(header) = &mut translation.header {
+                header.language = Some(language.code.clone());
+            };
+            let grouping = project_grouping(&project).grouping()?;
+            let stale = storage_mode
+                .write(&language.po, translation, &grouping)
+                .with_context(|| format!("can't create the {} translation", language.code))?;
+            print_stale_po_files(&stale, &grouping);
+        }
+    }
+    Ok(())
+}
+
+fn build(project_p: &ProjectParameter) -> Result<()> {
+    let project = Project::load(&project_p.project)?;
+    let languages = project.select_languages(project_p.language.as_deref())?;
+    if languages.is_empty() {
+        bail!("the project doesn't have any [[language]] to build");
+    };
+
+    for language in languages {
+        eprintln!(
+            "building the {} translation to {:?}",
+            language.code, language.output
+        );
+        if let Some(parent) = language.output.parent() {
+            create_dir_all(parent)
+                .with_context(|| format!("can't create the folder {:?}", parent))?;
+        };
+        frompo(&FromPoParameter {
+            mode: project.game.format.into(),
+            code_table: project.game.code_table.clone(),
+            storage_mode: project.po.storage,
+            input: language.po.clone(),
+            output: language.output.clone(),
+            base: if language.base {
+                Some(project.game.messages.clone())
+            } else {
+                None
+            },
+            fuzzy: language.fuzzy,
+            charset: project.game.charset.clone(),
+        })
+        .with_context(|| format!("can't build the {} translation", language.code))?;
+    }
     Ok(())
 }
@@ -0,0 +1,670 @@
+use pmd_code_table::CodeTable;
+use std::{collections::HashMap, fmt, num::IntErrorKind, str::FromStr};
+use thiserror::Error;
+
+/// Minimal similarity (as computed by [`strsim::normalized_levenshtein`], ignoring the case) for a placeholder of
+/// the code table to be suggested in place of an unknown one
+const MIN_SUGGESTION_SIMILARITY: f64 = 0.5;
+
+/// An error in the text of a message. The position is the index of the character where it was found.
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum MessageError {
+    #[error("character {0}: the character {1:?} is escaped, but only \\[ and \\\\ can be")]
+    UselessEscape(usize, char),
+    #[error("character {0}: the text end with an unfinished escape \\")]
+    UnfinishedEscape(usize),
+    #[error("character {0}: the placeholder isn't closed by a ]")]
+    UnclosedPlaceholder(usize),
+    #[error("character {0}: empty placeholder []")]
+    EmptyPlaceholder(usize),
+    #[error("character {0}: the placeholder [{1}] isn't in the code table")]
+    UnknownPlaceholder(usize, String),
+    #[error("character {0}: the argument {2:?} of the placeholder [{1}:] should be a number")]
+    InvalidArgument(usize, String, String),
+    #[error("character {0}: the argument {2} of the placeholder [{1}:] should be at most {3}")]
+    ArgumentTooBig(usize, String, String, u32),
+    #[error("character {0}: the placeholder [{1}] contain several :, but only one is allowed")]
+    TooManyParts(usize, String),
+}
+
+impl MessageError {
+    /// The index of the character where the error was found
+    pub fn position(&self) -> usize {
+        match self {
+            Self::UselessEscape(position, _)
+            | Self::UnfinishedEscape(position)
+            | Self::UnclosedPlaceholder(position)
+            | Self::EmptyPlaceholder(position)
+            | Self::UnknownPlaceholder(position, _)
+            | Self::InvalidArgument(position, _, _)
+            | Self::ArgumentTooBig(position, _, _, _)
+            | Self::TooManyParts(position, _) => *position,
+        }
+    }
+}
+
+/// A placeholder of a message, like ``[CENTER]`` or ``[VAR:5]``
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Placeholder {
+    /// The name of the placeholder, like ``CENTER`` or ``VAR``
+    pub name: String,
+    /// The argument written after a ``:``, like ``5`` in ``[VAR:5]``
+    pub argument: Option<String>,
+}
+
+impl Placeholder {
+    pub fn new(name: String, argument: Option<String>) -> Self {
+        Self { name, argument }
+    }
+}
+
+impl fmt::Display for Placeholder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.argument {
+            Some(argument) => write!(f, "[{}:{}]", self.name, argument),
+            None => write!(f, "[{}]", self.name),
+        }
+    }
+}
+
+/// A part of a [`Message`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessagePart {
+    /// Literal text, displayed as is (without the ``\`` escapes)
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+/// The text of a message, split in literal text and placeholders.
+///
+/// It is written like in the po files: ``[NAME]`` and ``[NAME:argument]`` are placeholders, and ``\[`` and ``\\``
+/// are a literal ``[`` and ``\``. Converting it back to a string (with [`fmt::Display`]) give the exact same text.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Message {
+    parts: Vec<MessagePart>,
+}
+
+impl Message {
+    /// Create a message from its parts. Adjacent texts are merged, and empty texts removed.
+    pub fn from_parts(parts: impl IntoIterator<Item = MessagePart>) -> Self {
+        let mut result = Self::default();
+        for part in parts {
+            match part {
+                MessagePart::Text(text) => result.push_text(&text),
+                MessagePart::Placeholder(placeholder) => result.push_placeholder(placeholder),
+            }
+        }
+        result
+    }
+
+    /// Parse the text of a message, only checking its syntax. The placeholder ``[NAME:argument]`` is split at
+    /// its first ``:``. Use [`PlaceholderTable::parse`] to check the placeholders with the code table.
+    pub fn parse(text: &str) -> Result<Self, MessageError> {
+        parse_message(text, |name, _| {
+            Ok(Some(match name.split_once(':') {
+                Some((name, argument)) => {
+                    Placeholder::new(name.to_string(), Some(argument.to_string()))
+                }
+                None => Placeholder::new(name.to_string(), None),
+            }))
+        })
+    }
+
+    pub fn parts(&self) -> &[MessagePart] {
+        &self.parts
+    }
+
+    pub fn into_parts(self) -> Vec<MessagePart> {
+        self.parts
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, MessagePart> {
+        self.parts.iter()
+    }
+
+    pub fn push_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        };
+        match self.parts.last_mut() {
+            Some(MessagePart::Text(last)) => last.push_str(text),
+            _ => self.parts.push(MessagePart::Text(text.to_string())),
+        }
+    }
+
+    pub fn push_placeholder(&mut self, placeholder: Placeholder) {
+        self.parts.push(MessagePart::Placeholder(placeholder));
+    }
+
+    /// Iterate over the placeholders of the message
+    pub fn placeholders(&self) -> impl Iterator<Item = &Placeholder> {
+        self.parts.iter().filter_map(|part| match part {
+            MessagePart::Placeholder(placeholder) => Some(placeholder),
+            MessagePart::Text(_) => None,
+        })
+    }
+
+    /// Return the literal text of the message, without the placeholders and the escapes
+    pub fn text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                MessagePart::Text(text) => Some(text.as_str()),
+                MessagePart::Placeholder(_) => None,
+            })
+            .collect()
+    }
+
+    /// Return true if a literal text of the message contain ``needle``. Placeholders are never matched.
+    pub fn contains_text(&self, needle: &str) -> bool {
+        self.parts.iter().any(|part| match part {
+            MessagePart::Text(text) => text.contains(needle),
+            MessagePart::Placeholder(_) => false,
+        })
+    }
+
+    /// Replace ``from`` by ``to`` in the literal texts of the message, leaving the placeholders untouched.
+    /// Return the number of replacements.
+    pub fn replace_text(&mut self, from: &str, to: &str) -> usize {
+        if from.is_empty() {
+            return 0;
+        };
+        let mut count = 0;
+        for part in &mut self.parts {
+            if let MessagePart::Text(text) = part {
+                let matches = text.matches(from).count();
+                if matches > 0 {
+                    *text = text.replace(from, to);
+                    count += matches;
+                };
+            };
+        }
+        count
+    }
+
+    /// Replace the placeholders equal to ``from`` by ``to``. Return the number of replacements.
+    pub fn replace_placeholder(&mut self, from: &Placeholder, to: &Placeholder) -> usize {
+        let mut count = 0;
+        for part in &mut self.parts {
+            if let MessagePart::Placeholder(placeholder) = part {
+                if placeholder == from {
+                    *placeholder = to.clone();
+                    count += 1;
+                };
+            };
+        }
+        count
+    }
+}
+
+impl<'a> IntoIterator for &'a Message {
+    type Item = &'a MessagePart;
+    type IntoIter = std::slice::Iter<'a, MessagePart>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                MessagePart::Text(text) => {
+                    for ch in text.chars() {
+                        match ch {
+                            '[' => f.write_str("\\[")?,
+                            '\\' => f.write_str("\\\\")?,
+                            ch => write!(f, "{}", ch)?,
+                        }
+                    }
+                }
+                MessagePart::Placeholder(placeholder) => write!(f, "{}", placeholder)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Message {
+    type Err = MessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Parse the text of a message, creating the placeholders from their content (between ``[`` and ``]``) and
+/// the position of their ``[`` with ``make_placeholder``. The placeholders it return ``None`` for are left out.
+fn parse_message(
+    text: &str,
+    mut make_placeholder: impl FnMut(&str, usize) -> Result<Option<Placeholder>, MessageError>,
+) -> Result<Message, MessageError> {
+    let mut message = Message::default();
+    let mut current_text = String::new();
+    let mut chars = text.chars().enumerate();
+    while let Some((position, ch)) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('[' | '\\'))) => current_text.push(escaped),
+                Some((_, escaped)) => return Err(MessageError::UselessEscape(position, escaped)),
+                None => return Err(MessageError::UnfinishedEscape(position)),
+            },
+            '[' => {
+                let mut content = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, ']')) => break,
+                        Some((_, placeholder_char)) => content.push(placeholder_char),
+                        None => return Err(MessageError::UnclosedPlaceholder(position)),
+                    }
+                }
+                if content.is_empty() {
+                    return Err(MessageError::EmptyPlaceholder(position));
+                };
+                message.push_text(&current_text);
+                current_text.clear();
+                if let Some(placeholder) = make_placeholder(&content, position)? {
+                    message.push_placeholder(placeholder);
+                };
+            }
+            ch => current_text.push(ch),
+        }
+    }
+    message.push_text(&current_text);
+    Ok(message)
+}
+
+/// The largest argument of a placeholder whose argument is stored in its own code
+const MAX_EMBEDDED_ARGUMENT: u32 = 255;
+
+/// The placeholders of a code table, used to parse and check the messages
+#[derive(Debug, Default, Clone)]
+pub struct PlaceholderTable {
+    /// The placeholders, like ``CENTER``, with their length in the code table. The ones taking an argument end
+    /// with ``:``, like ``VAR:``. Those with a length of 0 store their argument in the low byte of their code.
+    names: HashMap<String, u16>,
+}
+
+impl PlaceholderTable {
+    pub fn new(code_table: &CodeTable) -> Self {
+        Self::from_entries(
+            code_table
+                .entries()
+                .iter()
+                .map(|entry| (entry.string.clone(), entry.lenght)),
+        )
+    }
+
+    /// Create a table from the name and length of the placeholders, as in the code table
+    pub fn from_entries(entries: impl IntoIterator<Item = (String, u16)>) -> Self {
+        Self {
+            names: entries.into_iter().collect(),
+        }
+    }
+
+    /// Return true if the placeholder is in the code table
+    pub fn contains(&self, placeholder: &Placeholder) -> bool {
+        match &placeholder.argument {
+            None => self.names.contains_key(&placeholder.name),
+            Some(argument) => {
+                self.names.contains_key(&format!("{}:", placeholder.name))
+                    || self
+                        .names
+                        .contains_key(&format!("{}:{}", placeholder.name, argument))
+            }
+        }
+    }
+
+    /// Parse the text of a message, checking that its placeholders are in the code table. Placeholders whose
+    /// complete name contain a ``:`` (like ``[rubi:base]``) don't have an argument.
+    pub fn parse(&self, text: &str) -> Result<Message, MessageError> {
+        parse_message(text, |content, position| {
+            self.parse_placeholder(content, position).map(Some)
+        })
+    }
+
+    /// Parse the text of a message like [`PlaceholderTable::parse`], but without stopping at the first invalid
+    /// placeholder. The invalid placeholders are left out of the message, and their errors are returned with it.
+    /// Errors in the syntax of the text still stop the parsing.
+    pub fn parse_lenient(&self, text: &str) -> Result<(Message, Vec<MessageError>), MessageError> {
+        let mut errors = Vec::new();
+        let message = parse_message(text, |content, position| {
+            match self.parse_placeholder(content, position) {
+                Ok(placeholder) => Ok(Some(placeholder)),
+                Err(err) => {
+                    errors.push(err);
+                    Ok(None)
+                }
+            }
+        })?;
+        Ok((message, errors))
+    }
+
+    /// Return the name of the code table closest to the content of an unknown placeholder (like ``PARTNERNAM`` or
+    /// ``VAL:5``), if one is similar enough. Names taking an argument end with ``:``, like ``VAR:``.
+    pub fn suggest(&self, content: &str) -> Option<&str> {
+        let content = content.to_lowercase();
+        let name = content.split(':').next().unwrap_or("");
+        let mut best: Option<(f64, &str)> = None;
+        for candidate in self.names.keys() {
+            let lowercase_candidate = candidate.to_lowercase();
+            let similarity = match lowercase_candidate.strip_suffix(':') {
+                Some(candidate_name) => strsim::normalized_levenshtein(name, candidate_name),
+                None => strsim::normalized_levenshtein(&content, &lowercase_candidate),
+            };
+            // ties are broken by name, so the suggestion doesn't depend on the order of the set
+            let is_better = match best {
+                None => true,
+                Some((best_similarity, best_candidate)) => {
+                    similarity > best_similarity
+                        || (similarity == best_similarity && candidate.as_str() < best_candidate)
+                }
+            };
+            if is_better {
+                best = Some((similarity, candidate));
+            };
+        }
+        best.filter(|(similarity, _)| *similarity >= MIN_SUGGESTION_SIMILARITY)
+            .map(|(_, candidate)| candidate)
+    }
+
+    /// Parse the content of a placeholder (between ``[`` and ``]``), whose ``[`` is at ``position``. It is
+    /// refused in the same cases as when the message is encoded with the code table.
+    fn parse_placeholder(
+        &self,
+        content: &str,
+        position: usize,
+    ) -> Result<Placeholder, MessageError> {
+        if content.matches(':').count() > 1 {
+            return Err(MessageError::TooManyParts(position, content.to_string()));
+        };
+        if self.names.contains_key(content) {
+            return Ok(Placeholder::new(content.to_string(), None));
+        };
+        match content.split_once(':') {
+            Some((name, argument)) => {
+                let length = match self.names.get(&format!("{}:", name)) {
+                    Some(length) => *length,
+                    None => {
+                        return Err(MessageError::UnknownPlaceholder(
+                            position,
+                            content.to_string(),
+                        ))
+                    }
+                };
+                let max = if length == 0 {
+                    MAX_EMBEDDED_ARGUMENT
+                } else {
+                    u32::MAX
+                };
+                let too_big = || {
+                    MessageError::ArgumentTooBig(
+                        position,
+                        name.to_string(),
+                        argument.to_string(),
+                        max,
+                    )
+                };
+                match u32::from_str(argument) {
+                    Ok(value) if value > max => return Err(too_big()),
+                    Ok(_) => (),
+                    Err(err) if *err.kind() == IntErrorKind::PosOverflow => return Err(too_big()),
+                    Err(_) => {
+                        return Err(MessageError::InvalidArgument(
+                            position,
+                            name.to_string(),
+                            argument.to_string(),
+                        ))
+                    }
+                };
+                Ok(Placeholder::new(
+                    name.to_string(),
+                    Some(argument.to_string()),
+                ))
+            }
+            _ => Err(MessageError::UnknownPlaceholder(
+                position,
+                content.to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> PlaceholderTable {
+        PlaceholderTable::from_entries(
+            [
+                ("CENTER", 0),
+                ("PARTNERNAME", 0),
+                ("VAR:", 1),
+                ("COLOR:", 0),
+                ("rubi:base", 0),
+                ("K", 0),
+            ]
+            .iter()
+            .map(|(name, length)| (name.to_string(), *length)),
+        )
+    }
+
+    fn text(text: &str) -> MessagePart {
+        MessagePart::Text(text.to_string())
+    }
+
+    fn placeholder(name: &str, argument: Option<&str>) -> MessagePart {
+        MessagePart::Placeholder(Placeholder::new(
+            name.to_string(),
+            argument.map(str::to_string),
+        ))
+    }
+
+    #[test]
+    fn messages_are_written_back_identically() {
+        for source in [
+            "",
+            "Hello",
+            "\\[not a placeholder] and \\\\ a backslash]",
+            "\\\\[CENTER]",
+            "[VAR:5] apples",
+            "[CENTER][PARTNERNAME][K]",
+            "A[rubi:base]B",
+            "Line\nbreak",
+        ] {
+            let message = Message::parse(source).unwrap();
+            assert_eq!(message.to_string(), source);
+            let message = table().parse(source).unwrap();
+            assert_eq!(message.to_string(), source);
+            let (message, errors) = table().parse_lenient(source).unwrap();
+            assert!(errors.is_empty());
+            assert_eq!(message.to_string(), source);
+        }
+    }
+
+    #[test]
+    fn parse_parts() {
+        assert_eq!(
+            Message::parse("\\[a\\\\[VAR:5][CENTER][K]b")
+                .unwrap()
+                .parts(),
+            [
+                text("[a\\"),
+                placeholder("VAR", Some("5")),
+                placeholder("CENTER", None),
+                placeholder("K", None),
+                text("b"),
+            ]
+        );
+        // without a code table, every placeholder is split at its first :
+        assert_eq!(
+            Message::parse("[rubi:base:x]").unwrap().parts(),
+            [placeholder("rubi", Some("base:x"))]
+        );
+        assert_eq!(
+            table().parse("[rubi:base]").unwrap().parts(),
+            [placeholder("rubi:base", None)]
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(
+            Message::parse("ab\\n"),
+            Err(MessageError::UselessEscape(2, 'n'))
+        );
+        assert_eq!(
+            Message::parse("ab\\"),
+            Err(MessageError::UnfinishedEscape(2))
+        );
+        assert_eq!(
+            Message::parse("a[CENTER"),
+            Err(MessageError::UnclosedPlaceholder(1))
+        );
+        assert_eq!(
+            Message::parse("a[]"),
+            Err(MessageError::EmptyPlaceholder(1))
+        );
+        // the syntax errors stop the lenient parsing too
+        assert_eq!(
+            table().parse_lenient("[UNKNOWN] \\"),
+            Err(MessageError::UnfinishedEscape(10))
+        );
+    }
+
+    #[test]
+    fn placeholders_are_checked() {
+        let table = table();
+        assert_eq!(
+            table.parse("a [PARTNERNAM] b"),
+            Err(MessageError::UnknownPlaceholder(
+                2,
+                "PARTNERNAM".to_string()
+            ))
+        );
+        // the argument of a placeholder that doesn't take one is unknown
+        assert_eq!(
+            table.parse("[CENTER:5]"),
+            Err(MessageError::UnknownPlaceholder(0, "CENTER:5".to_string()))
+        );
+        assert_eq!(
+            Message::parse("[CENTER:5]").unwrap().parts(),
+            [placeholder("CENTER", Some("5"))]
+        );
+        assert_eq!(
+            table.parse("[VAR]"),
+            Err(MessageError::UnknownPlaceholder(0, "VAR".to_string()))
+        );
+        for argument in ["x", "-1", "5 "] {
+            assert_eq!(
+                table.parse(&format!("[VAR:{}]", argument)),
+                Err(MessageError::InvalidArgument(
+                    0,
+                    "VAR".to_string(),
+                    argument.to_string()
+                ))
+            );
+        }
+        // like in the code table, the name of a placeholder taking an argument is also valid alone
+        assert_eq!(
+            table.parse("[VAR:]").unwrap().parts(),
+            [placeholder("VAR:", None)]
+        );
+
+        let (message, errors) = table
+            .parse_lenient("[UNKNOWN]a[CENTER][VAR:x]b[OTHER]")
+            .unwrap();
+        assert_eq!(
+            message.parts(),
+            [text("a"), placeholder("CENTER", None), text("b")]
+        );
+        assert_eq!(
+            errors,
+            [
+                MessageError::UnknownPlaceholder(0, "UNKNOWN".to_string()),
+                MessageError::InvalidArgument(18, "VAR".to_string(), "x".to_string()),
+                MessageError::UnknownPlaceholder(26, "OTHER".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn arguments_are_checked_like_the_encoder() {
+        let table = table();
+        assert_eq!(
+            table.parse("a[VAR:1:2]"),
+            Err(MessageError::TooManyParts(1, "VAR:1:2".to_string()))
+        );
+        assert_eq!(
+            table.parse("[rubi:base:x]"),
+            Err(MessageError::TooManyParts(0, "rubi:base:x".to_string()))
+        );
+        assert_eq!(
+            table.parse("[VAR:4294967296]"),
+            Err(MessageError::ArgumentTooBig(
+                0,
+                "VAR".to_string(),
+                "4294967296".to_string(),
+                u32::MAX
+            ))
+        );
+        assert!(table.parse("[VAR:4294967295]").is_ok());
+        // the argument of a placeholder of length 0 is stored in its code
+        assert!(table.parse("[COLOR:255]").is_ok());
+        assert_eq!(
+            table.parse("[COLOR:256]"),
+            Err(MessageError::ArgumentTooBig(
+                0,
+                "COLOR".to_string(),
+                "256".to_string(),
+                255
+            ))
+        );
+        assert_eq!(
+            table.parse("[VAR:+5]").unwrap().parts(),
+            [placeholder("VAR", Some("+5"))]
+        );
+    }
+
+    #[test]
+    fn suggest_placeholders() {
+        let table = table();
+        assert_eq!(table.suggest("PARTNERNAM"), Some("PARTNERNAME"));
+        assert_eq!(table.suggest("center"), Some("CENTER"));
+        assert_eq!(table.suggest("VAL:5"), Some("VAR:"));
+        assert_eq!(table.suggest("SOMETHING_ELSE"), None);
+    }
+
+    #[test]
+    fn replace_in_message() {
+        let mut message = Message::parse("Yes [VAR:1] Yes\\[Yes] [CENTER]").unwrap();
+        assert_eq!(message.replace_text("Yes", "No"), 3);
+        assert_eq!(message.to_string(), "No [VAR:1] No\\[No] [CENTER]");
+        // placeholders are never changed by replace_text
+        assert_eq!(message.replace_text("CENTER", "x"), 0);
+        assert_eq!(message.replace_text("", "x"), 0);
+
+        let var = Placeholder::new("VAR".to_string(), Some("1".to_string()));
+        let other_var = Placeholder::new("VAR".to_string(), Some("2".to_string()));
+        assert_eq!(message.replace_placeholder(&other_var, &var), 0);
+        assert_eq!(message.replace_placeholder(&var, &other_var), 1);
+        assert_eq!(message.to_string(), "No [VAR:2] No\\[No] [CENTER]");
+        assert!(message.contains_text("[No]"));
+        assert!(!message.contains_text("CENTER"));
+        assert_eq!(message.text(), "No  No[No] ");
+    }
+
+    #[test]
+    fn from_parts_merge_texts() {
+        let message = Message::from_parts([
+            text("a"),
+            text(""),
+            text("["),
+            placeholder("CENTER", None),
+            text(""),
+        ]);
+        assert_eq!(message.parts(), [text("a["), placeholder("CENTER", None)]);
+        assert_eq!(message.to_string(), "a\\[[CENTER]");
+    }
+}
@@ -0,0 +1,619 @@
+use crate::{Entry, GettextWriter, PoErrors};
+use pmd_code_table::{CodeTable, CodeToText, TextToCode};
+use pmd_farc::{
+    hash_name, message_dehash::try_possible_name, Farc, FarcError, FarcWriter, FarcWriterError,
+};
+use pmd_message::{MessageBin, MessageBinReadError, MessageBinWriteError};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fs::{copy, create_dir_all, read_dir, File},
+    io::{self, BufReader, Cursor, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+};
+use thiserror::Error;
+
+/// The messages of the game, with their translation
+pub type Catalog = GettextWriter;
+
+/// The message file of a folder that isn't extracted nor rebuilt, as it isn't a normal message file
+pub const NAME_SORT_FILE: &str = "name_sort.bin";
+
+#[derive(Debug, Error)]
+/// An error that happened while extracting or rebuilding the messages of the game
+pub enum PipelineError {
+    #[error("input/output error with the file {0:?}")]
+    Io(PathBuf, #[source] io::Error),
+    #[error("can't read the list file of the farc")]
+    ListFile(#[source] io::Error),
+    #[error("can't read the farc")]
+    Farc(#[from] FarcError),
+    #[error("can't write the farc")]
+    FarcWriter(#[from] FarcWriterError),
+    #[error("can't read the {0:?} message file")]
+    ReadMessage(String, #[source] MessageBinReadError),
+    #[error("can't encode the {0:?} message file")]
+    WriteMessage(String, #[source] MessageBinWriteError),
+    #[error("the file name {0:?} isn't valid utf-8")]
+    InvalidFileName(OsString),
+    #[error(
+        "the source file {0:?} isn't a file name: it would be written outside of the output folder"
+    )]
+    UnsafeSourceFile(String),
+    #[error("can't parse the po file at {0:?}")]
+    Po(PathBuf, #[source] PoErrors),
+}
+
+/// Return a function that attach the given path to an [`io::Error`]
+pub(crate) fn io_error(path: &Path) -> impl FnOnce(io::Error) -> PipelineError + '_ {
+    move |err| PipelineError::Io(path.to_path_buf(), err)
+}
+
+/// The name given to the files of a farc whose real name is unknown
+pub fn unnamed_file_name(hash: u32) -> String {
+    format!("hash_0x{:08X}", hash)
+}
+
+/// Return the hash of a farc file name, that may be a name generated by [`unnamed_file_name`] (``hash_0x`` followed
+/// by the 8 hexadecimal digits of the hash)
+pub fn hash_of_file_name(file_name: &str) -> u32 {
+    file_name
+        .strip_prefix("hash_0x")
+        .filter(|hash| hash.len() == 8 && hash.chars().all(|ch| ch.is_ascii_hexdigit()))
+        .and_then(|hash| u32::from_str_radix(hash, 16).ok())
+        .unwrap_or_else(|| hash_name(file_name))
+}
+
+/// Put the messages of ``translated`` on top of the ``original`` message file, keeping the other messages
+fn overlay_message_bin(mut original: MessageBin, translated: &MessageBin) -> MessageBin {
+    for (hash, unk, text) in translated.messages() {
+        original.insert(*hash, *unk, text.clone());
+    }
+    original
+}
+
+fn read_message_bin<F: Read + Seek>(
+    file: &mut F,
+    file_name: &str,
+    code_to_text: &CodeToText,
+) -> Result<MessageBin, PipelineError> {
+    MessageBin::load_file(file, Some(code_to_text))
+        .map_err(|err| PipelineError::ReadMessage(file_name.to_string(), err))
+}
+
+/// Open a message farc, finding the name of its files with its list file (like ``message_us.lst``)
+pub fn open_message_farc<F: Read + Seek, L: Read>(
+    reader: F,
+    mut list_reader: L,
+) -> Result<Farc<F>, PipelineError> {
+    let mut farc = Farc::new(reader)?;
+    try_possible_name(&mut farc, &mut list_reader).map_err(PipelineError::ListFile)?;
+    Ok(farc)
+}
+
+/// Extract the messages of a message farc, whose files are named with its list file.
+/// Files with an unknown name are named with [`unnamed_file_name`].
+pub fn extract_farc<F: Read + Seek, L: Read>(
+    reader: F,
+    list_reader: L,
+    code_table: &CodeTable,
+) -> Result<Catalog, PipelineError> {
+    extract_message_farc(&open_message_farc(reader, list_reader)?, code_table)
+}
+
+/// Extract the messages of an already opened message farc. See [`extract_farc`].
+pub fn extract_message_farc<F: Read + Seek>(
+    farc: &Farc<F>,
+    code_table: &CodeTable,
+) -> Result<Catalog, PipelineError> {
+    let code_to_text = code_table.generate_code_to_text();
+    let mut catalog = Catalog::new(Vec::new());
+    for (hash, file_name) in farc.iter() {
+        let file_name = file_name
+            .cloned()
+            .unwrap_or_else(|| unnamed_file_name(hash));
+        let mut message_file = farc.get_hashed_file(hash)?;
+        let message_bin = read_message_bin(&mut message_file, &file_name, &code_to_text)?;
+        catalog.push_message_bin(&message_bin, &file_name);
+    }
+    Ok(catalog)
+}
+
+/// Extract the messages of every message file of a folder, except [`NAME_SORT_FILE`]
+pub fn extract_folder(path: &Path, code_table: &CodeTable) -> Result<Catalog, PipelineError> {
+    let code_to_text = code_table.generate_code_to_text();
+    let mut catalog = Catalog::new(Vec::new());
+    for file_entry in read_dir(path).map_err(io_error(path))? {
+        let file_entry = file_entry.map_err(io_error(path))?;
+        let file_name = file_name_string(file_entry.file_name())?;
+        if file_name == NAME_SORT_FILE {
+            continue;
+        };
+        let file_path = file_entry.path();
+        let mut file = BufReader::new(File::open(&file_path).map_err(io_error(&file_path))?);
+        let message_bin = read_message_bin(&mut file, &file_name, &code_to_text)?;
+        catalog.push_message_bin(&message_bin, &file_name);
+    }
+    Ok(catalog)
+}
+
+fn file_name_string(file_name: OsString) -> Result<String, PipelineError> {
+    file_name
+        .into_string()
+        .map_err(PipelineError::InvalidFileName)
+}
+
+impl GettextWriter {
+    /// Add the messages of a message file, that come from the ``source_file`` file
+    pub fn push_message_bin(&mut self, message_bin: &MessageBin, source_file: &str) {
+        for (index, (hash, unk, text)) in message_bin.messages().iter().enumerate() {
+            let mut entry = Entry::new(text.clone(), *hash, *unk, source_file.to_string());
+            entry.index = Some(index);
+            self.entries.push(entry);
+        }
+    }
+
+    /// Group the messages by the file they come from, with the text they should have in game. They are put back
+    /// at their position in the file when it is known, so a file is rebuilt with its messages in the same order.
+    pub fn to_message_bins(&self) -> BTreeMap<String, MessageBin> {
+        let mut files: BTreeMap<&str, Vec<&Entry>> = BTreeMap::new();
+        for entry in self.entries.iter() {
+            files.entry(&entry.source_file).or_default().push(entry);
+        }
+        files
+            .into_iter()
+            .map(|(source_file, entries)| {
+                let mut message_bin = MessageBin::default();
+                for entry in in_file_order(entries) {
+                    message_bin.insert(entry.hash, entry.unk, entry.translated_text().to_string());
+                }
+                (source_file.to_string(), message_bin)
+            })
+            .collect()
+    }
+}
+
+/// Order the messages of a file: the ones whose index is known are put at this position, and the other ones
+/// fill the remaining places, in their current order
+fn in_file_order(entries: Vec<&Entry>) -> Vec<&Entry> {
+    let mut places: Vec<Option<&Entry>> = vec![None; entries.len()];
+    let mut unplaced = Vec::new();
+    for entry in entries {
+        match entry.index {
+            Some(index) if index < places.len() && places[index].is_none() => {
+                places[index] = Some(entry)
+            }
+            _ => unplaced.push(entry),
+        }
+    }
+    let mut unplaced = unplaced.into_iter();
+    places
+        .into_iter()
+        .filter_map(|place| place.or_else(|| unplaced.next()))
+        .collect()
+}
+
+fn encode_message_bin(
+    message_bin: &MessageBin,
+    file_name: &str,
+    text_to_code: &TextToCode,
+) -> Result<Vec<u8>, PipelineError> {
+    let mut buffer = Cursor::new(Vec::new());
+    message_bin
+        .write(&mut buffer, Some(text_to_code))
+        .map_err(|err| PipelineError::WriteMessage(file_name.to_string(), err))?;
+    Ok(buffer.into_inner())
+}
+
+/// Build a message farc containing the messages of the catalog, with their translation
+pub fn build_farc<W: Write + Seek>(
+    catalog: &Catalog,
+    code_table: &CodeTable,
+    writer: &mut W,
+) -> Result<(), PipelineError> {
+    let text_to_code = code_table.generate_text_to_code();
+    let mut farc_writer = FarcWriter::default();
+    for (file_name, message_bin) in catalog.to_message_bins() {
+        farc_writer.add_hashed_file(
+            hash_of_file_name(&file_name),
+            encode_message_bin(&message_bin, &file_name, &text_to_code)?,
+        );
+    }
+    farc_writer.write_hashed(writer)?;
+    Ok(())
+}
+
+/// Read a file of a farc, so that [`FarcWriter`] write it back identically. The files of a farc are padded to 16
+/// bytes, and this padding is counted in their length, so [`FarcWriter`] pad them again: the last padding byte
+/// is removed, so the padding it add is the one that was read.
+fn read_unpadded_farc_file<F: Read + Seek>(
+    farc: &Farc<F>,
+    hash: u32,
+) -> Result<Vec<u8>, PipelineError> {
+    let mut content = Vec::new();
+    farc.get_hashed_file(hash)?
+        .read_to_end(&mut content)
+        .map_err(FarcError::IOerror)?;
+    if content.len() % 16 == 0 && content.last() == Some(&0) {
+        content.pop();
+    };
+    Ok(content)
+}
+
+/// Build a message farc like [`build_farc`], but keep the files and messages of ``base`` that aren't in the catalog.
+/// Return the number of files kept untouched from ``base``.
+pub fn build_farc_on_base<F: Read + Seek, W: Write + Seek>(
+    catalog: &Catalog,
+    code_table: &CodeTable,
+    base: &Farc<F>,
+    writer: &mut W,
+) -> Result<usize, PipelineError> {
+    let code_to_text = code_table.generate_code_to_text();
+    let text_to_code = code_table.generate_text_to_code();
+    let mut farc_writer = FarcWriter::default();
+    for hash in base.iter_all_hash() {
+        farc_writer.add_hashed_file(*hash, read_unpadded_farc_file(base, *hash)?);
+    }
+    let mut translated_hashes = BTreeSet::new();
+    for (file_name, message_bin) in catalog.to_message_bins() {
+        let hash = hash_of_file_name(&file_name);
+        let message_bin = match base.get_hashed_file(hash) {
+            Ok(mut original_file) => overlay_message_bin(
+                read_message_bin(&mut original_file, &file_name, &code_to_text)?,
+                &message_bin,
+            ),
+            // a new file, that isn't in the original archive
+            Err(FarcError::HashedFileNotFound(_)) => message_bin,
+            Err(err) => return Err(err.into()),
+        };
+        farc_writer.add_hashed_file(
+            hash,
+            encode_message_bin(&message_bin, &file_name, &text_to_code)?,
+        );
+        translated_hashes.insert(hash);
+    }
+    farc_writer.write_hashed(writer)?;
+    Ok(base
+        .iter_all_hash()
+        .filter(|hash| !translated_hashes.contains(hash))
+        .count())
+}
+
+/// Return true if ``file_name`` is a plain file name, that can't refer to a file outside of a folder (like
+/// ``../file.bin`` or ``/file.bin``)
+pub(crate) fn is_plain_file_name(file_name: &str) -> bool {
+    let mut components = Path::new(file_name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) && !file_name.contains(['/', '\\'])
+}
+
+/// The message files of the catalog, to be written in a folder. Their source file come from the po files, so they
+/// are checked to be plain file names, that can't be written outside of the folder.
+fn folder_message_bins(catalog: &Catalog) -> Result<BTreeMap<String, MessageBin>, PipelineError> {
+    let message_bins = catalog.to_message_bins();
+    for file_name in message_bins.keys() {
+        if !is_plain_file_name(file_name) {
+            return Err(PipelineError::UnsafeSourceFile(file_name.clone()));
+        };
+    }
+    Ok(message_bins)
+}
+
+fn write_message_folder(
+    message_bins: BTreeMap<String, MessageBin>,
+    code_table: &CodeTable,
+    output: &Path,
+) -> Result<(), PipelineError> {
+    let text_to_code = code_table.generate_text_to_code();
+    create_dir_all(output).map_err(io_error(output))?;
+    for (file_name, message_bin) in message_bins {
+        let file_path = output.join(&file_name);
+        let content = encode_message_bin(&message_bin, &file_name, &text_to_code)?;
+        File::create(&file_path)
+            .and_then(|mut file| file.write_all(&content))
+            .map_err(io_error(&file_path))?;
+    }
+    Ok(())
+}
+
+/// Write one message file per source file of the catalog in the ``output`` folder
+pub fn build_folder(
+    catalog: &Catalog,
+    code_table: &CodeTable,
+    output: &Path,
+) -> Result<(), PipelineError> {
+    write_message_folder(folder_message_bins(catalog)?, code_table, output)
+}
+
+/// Build a message folder like [`build_folder`], but keep the files and messages of the ``base`` folder that
+/// aren't in the catalog. ``base`` and ``output`` may be the same folder. Return the number of files kept untouched
+/// from ``base``.
+pub fn build_folder_on_base(
+    catalog: &Catalog,
+    code_table: &CodeTable,
+    base: &Path,
+    output: &Path,
+) -> Result<usize, PipelineError> {
+    let code_to_text = code_table.generate_code_to_text();
+    let mut message_bins = folder_message_bins(catalog)?;
+    create_dir_all(output).map_err(io_error(output))?;
+    let mut kept_files = 0;
+    for file_entry in read_dir(base).map_err(io_error(base))? {
+        let file_entry = file_entry.map_err(io_error(base))?;
+        let file_name = file_name_string(file_entry.file_name())?;
+        let base_path = file_entry.path();
+        if let Some(message_bin) = message_bins.remove(&file_name) {
+            let mut file = BufReader::new(File::open(&base_path).map_err(io_error(&base_path))?);
+            let original = read_message_bin(&mut file, &file_name, &code_to_text)?;
+            message_bins.insert(file_name, overlay_message_bin(original, &message_bin));
+        } else {
+            let output_path = output.join(&file_name);
+            if base_path.canonicalize().map_err(io_error(&base_path))?
+                != output_path.canonicalize().unwrap_or_default()
+            {
+                copy(&base_path, &output_path).map_err(io_error(&output_path))?;
+            }
+            kept_files += 1;
+        }
+    }
+    write_message_folder(message_bins, code_table, output)?;
+    Ok(kept_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FuzzyPolicy, PoWarning};
+    use std::{env, fs::remove_dir_all};
+
+    fn test_folder(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("pmdtranslate-{}-{}", name, std::process::id()));
+        if path.exists() {
+            remove_dir_all(&path).unwrap();
+        };
+        path
+    }
+
+    /// The original messages of the game, with a file the translation doesn't contain
+    fn base_catalog() -> Catalog {
+        let mut script = MessageBin::default();
+        script.insert(1, 0, "Yes".to_string());
+        script.insert(2, 3, "No".to_string());
+        let mut menu = MessageBin::default();
+        menu.insert(10, 0, "Back".to_string());
+        let mut catalog = Catalog::new(Vec::new());
+        catalog.push_message_bin(&script, "script.bin");
+        catalog.push_message_bin(&menu, "menu.bin");
+        catalog
+    }
+
+    /// A translation of only the first message of ``script.bin``
+    fn partial_translation(translation: &str) -> Catalog {
+        let mut catalog = Catalog::new(Vec::new());
+        let mut entry = Entry::new("Yes".to_string(), 1, 0, "script.bin".to_string());
+        entry.translation = Some(translation.to_string());
+        catalog.entries.push(entry);
+        catalog
+    }
+
+    fn read_farc_file(farc: &Farc<Cursor<Vec<u8>>>, file_name: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        farc.get_hashed_file(hash_name(file_name))
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn unnamed_file_names_keep_their_hash() {
+        for hash in [0, 1, 0xABCD_0123, u32::MAX] {
+            assert_eq!(hash_of_file_name(&unnamed_file_name(hash)), hash);
+        }
+        assert_eq!(unnamed_file_name(0xABC), "hash_0x00000ABC");
+        assert_eq!(hash_of_file_name("hash_0x00000abc"), 0xABC);
+        assert_eq!(hash_of_file_name("script.bin"), hash_name("script.bin"));
+
+        // other names starting like an unnamed file are normal file names
+        for file_name in [
+            "hash_0x",
+            "hash_0xGHIJKLMN",
+            "hash_0x+0000001",
+            "hash_0x123",
+            "hash_0x123456789",
+            "hash_0x00000001.bin",
+        ] {
+            assert_eq!(
+                hash_of_file_name(file_name),
+                hash_name(file_name),
+                "{:?}",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn messages_keep_their_order_through_the_pot_file() {
+        let mut original = MessageBin::default();
+        original.insert(1, 0, "Yes".to_string());
+        original.insert(2, 0, "No".to_string());
+        original.insert(3, 5, "Yes".to_string());
+        let mut catalog = Catalog::new(Vec::new());
+        catalog.push_message_bin(&original, "script.bin");
+
+        let (reparsed, _) = Catalog::from_po(catalog.to_pot(), FuzzyPolicy::Use).unwrap();
+        let rebuilt = reparsed.to_message_bins();
+        assert_eq!(rebuilt["script.bin"].messages(), original.messages());
+    }
+
+    #[test]
+    fn unmodified_catalog_is_rebuilt_identically() {
+        let mut script = MessageBin::default();
+        for (hash, unk, text) in [
+            (10, 0, "Yes"),
+            (11, 0, "No"),
+            (12, 3, "Yes"),
+            (13, 0, ""),
+            (14, 0, "No"),
+            (15, 0, "Line\nbreak and \\[CENTER]"),
+            (16, 1, "Yes"),
+            (17, 0, ""),
+        ] {
+            script.insert(hash, unk, text.to_string());
+        }
+        let mut menu = MessageBin::default();
+        menu.insert(30, 0, "No".to_string());
+        menu.insert(31, 0, "Back".to_string());
+        menu.insert(32, 0, "Yes".to_string());
+        menu.insert(33, 0, "Back".to_string());
+        let mut catalog = Catalog::new(vec!["back".to_string()]);
+        catalog.push_message_bin(&script, "script.bin");
+        catalog.push_message_bin(&menu, "menu.bin");
+
+        let (reparsed, _) = Catalog::from_po(catalog.to_pot(), FuzzyPolicy::Use).unwrap();
+        let rebuilt = reparsed.to_message_bins();
+        assert_eq!(rebuilt.len(), 2);
+        assert_eq!(rebuilt["script.bin"].messages(), script.messages());
+        assert_eq!(rebuilt["menu.bin"].messages(), menu.messages());
+    }
+
+    #[test]
+    fn removed_location_flags_are_reported() {
+        let mut script = MessageBin::default();
+        script.insert(1, 0, "Yes".to_string());
+        script.insert(2, 4, "Yes".to_string());
+        let mut catalog = Catalog::new(Vec::new());
+        catalog.push_message_bin(&script, "script.bin");
+        let pot = catalog.to_pot();
+
+        let (_, warnings) = Catalog::from_po(pot.clone(), FuzzyPolicy::Use).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        // like a tool that doesn't keep the unknown flags
+        let stripped: String = pot
+            .lines()
+            .filter(|line| !line.starts_with("#, pmd-"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_ne!(stripped, pot);
+        let (_, warnings) = Catalog::from_po(stripped, FuzzyPolicy::Use).unwrap();
+        let missing: Vec<&str> = warnings
+            .iter()
+            .filter_map(|warning| match warning {
+                PoWarning::MissingLocationFlags(_, reference) => Some(reference.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(missing, ["script.bin:0x00000001", "script.bin:0x00000002"]);
+    }
+
+    #[test]
+    fn farc_base_keep_what_isnt_translated() {
+        let code_table = CodeTable::default();
+        let mut base = Cursor::new(Vec::new());
+        build_farc(&base_catalog(), &code_table, &mut base).unwrap();
+        let base = Farc::new(Cursor::new(base.into_inner())).unwrap();
+
+        let mut output = Cursor::new(Vec::new());
+        let kept = build_farc_on_base(&partial_translation("Oui"), &code_table, &base, &mut output)
+            .unwrap();
+        assert_eq!(kept, 1);
+        let output = Farc::new(Cursor::new(output.into_inner())).unwrap();
+        assert_eq!(
+            read_farc_file(&output, "menu.bin"),
+            read_farc_file(&base, "menu.bin")
+        );
+        let script = read_message_bin(
+            &mut Cursor::new(read_farc_file(&output, "script.bin")),
+            "script.bin",
+            &code_table.generate_code_to_text(),
+        )
+        .unwrap();
+        assert_eq!(
+            script.messages(),
+            &vec![(1, 0, "Oui".to_string()), (2, 3, "No".to_string())]
+        );
+
+        // translated with the original text, the file is rebuilt as it was
+        let mut output = Cursor::new(Vec::new());
+        build_farc_on_base(&partial_translation("Yes"), &code_table, &base, &mut output).unwrap();
+        let output = Farc::new(Cursor::new(output.into_inner())).unwrap();
+        assert_eq!(
+            read_farc_file(&output, "script.bin"),
+            read_farc_file(&base, "script.bin")
+        );
+    }
+
+    #[test]
+    fn folder_base_keep_what_isnt_translated() {
+        let code_table = CodeTable::default();
+        let base = test_folder("pipeline-base");
+        build_folder(&base_catalog(), &code_table, &base).unwrap();
+
+        let output = test_folder("pipeline-output");
+        let kept =
+            build_folder_on_base(&partial_translation("Oui"), &code_table, &base, &output).unwrap();
+        assert_eq!(kept, 1);
+        assert_eq!(
+            std::fs::read(output.join("menu.bin")).unwrap(),
+            std::fs::read(base.join("menu.bin")).unwrap()
+        );
+        let script = read_message_bin(
+            &mut File::open(output.join("script.bin")).unwrap(),
+            "script.bin",
+            &code_table.generate_code_to_text(),
+        )
+        .unwrap();
+        assert_eq!(
+            script.messages(),
+            &vec![(1, 0, "Oui".to_string()), (2, 3, "No".to_string())]
+        );
+
+        build_folder_on_base(&partial_translation("Yes"), &code_table, &base, &output).unwrap();
+        assert_eq!(
+            std::fs::read(output.join("script.bin")).unwrap(),
+            std::fs::read(base.join("script.bin")).unwrap()
+        );
+
+        remove_dir_all(&base).unwrap();
+        remove_dir_all(&output).unwrap();
+    }
+
+    #[test]
+    fn folder_source_files_are_plain_file_names() {
+        for source_file in [
+            "../../x.bin",
+            "/tmp/x.bin",
+            "sub/x.bin",
+            "sub\\x.bin",
+            "..",
+            "",
+        ] {
+            let mut catalog = Catalog::new(Vec::new());
+            catalog.entries.push(Entry::new(
+                "text".to_string(),
+                1,
+                0,
+                source_file.to_string(),
+            ));
+            assert!(
+                matches!(
+                    folder_message_bins(&catalog),
+                    Err(PipelineError::UnsafeSourceFile(_))
+                ),
+                "{:?} was accepted",
+                source_file
+            );
+        }
+        let mut catalog = Catalog::new(Vec::new());
+        catalog.entries.push(Entry::new(
+            "text".to_string(),
+            1,
+            0,
+            "script.bin".to_string(),
+        ));
+        assert!(folder_message_bins(&catalog).is_ok());
+    }
+}
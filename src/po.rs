@@ -0,0 +1,951 @@
+//! A parser for the gettext po/pot file format, independent of how pmdtranslate use the entries.
+use crate::escape_string_for_gettext;
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+    num::ParseIntError,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// A non fatal parsing error of Po file. The user should likely be informed of these
+pub enum PoWarning {
+    #[error("the line {0} start with the unknown symbol {1:?}")]
+    LineTypeUnknown(usize, String),
+    #[error("the line {0} end with an unclosed quote")]
+    UnclosedQuote(usize),
+    #[error("the line {0} end with an escape character")]
+    UnfinishedEscape(usize),
+    #[error("the translation of {1:?} (at line {0}) is marked as fuzzy")]
+    FuzzyTranslation(usize, String),
+    #[error("the reference {1:?} (at line {0}) doesn't have a pmd-unk flag. The flags were likely removed by the software that edited the file: its unk value is read as 0, and its position in its message file may change")]
+    MissingLocationFlags(usize, String),
+}
+
+#[derive(Debug, Error)]
+/// A fatal error in a Po file. They contain the line and column (both starting at 1) and the offending text.
+pub enum PoError {
+    #[error(
+        "line {0}, column {1}: the escape sequence {2:?} should be of the form \\\\x{{<hex code>}}"
+    )]
+    InvalidHexEscape(usize, usize, String),
+    #[error("line {0}, column {1}: the escape sequence {2:?} isn't closed with a }}")]
+    UnclosedHexEscape(usize, usize, String),
+    #[error("line {0}, column {1}: {2:?} isn't a valid hexadecimal number")]
+    InvalidHexNumber(usize, usize, String, #[source] ParseIntError),
+    #[error("line {0}, column {1}: {2:?} isn't a valid unicode character code")]
+    InvalidCharCode(usize, usize, String),
+    #[error("line {0}, column {1}: the entry {2:?} doesn't have a reference (#: <source file>:0x<hash>)")]
+    MissingLocation(usize, usize, String),
+    #[error(
+        "line {0}, column {1}: the reference {2:?} should be of the form <source file>:0x<hash>"
+    )]
+    MalformedReference(usize, usize, String),
+    #[error("line {0}, column {1}: the location comment {2:?} should be of the form <source file> <hash> <unk>")]
+    MalformedLocation(usize, usize, String),
+    #[error("line {0}, column {1}: {2:?} isn't a valid 32 bit number")]
+    InvalidNumber(usize, usize, String, #[source] ParseIntError),
+    #[error("line {0}, column {1}: the translation of {2:?} is marked as fuzzy. Review it before building.")]
+    FuzzyTranslation(usize, usize, String),
+}
+
+impl PoError {
+    /// Return the line this error happened at
+    pub fn line(&self) -> usize {
+        match self {
+            Self::InvalidHexEscape(line, _, _)
+            | Self::UnclosedHexEscape(line, _, _)
+            | Self::InvalidHexNumber(line, _, _, _)
+            | Self::InvalidCharCode(line, _, _)
+            | Self::MissingLocation(line, _, _)
+            | Self::MalformedLocation(line, _, _)
+            | Self::MalformedReference(line, _, _)
+            | Self::InvalidNumber(line, _, _, _)
+            | Self::FuzzyTranslation(line, _, _) => *line,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// All the [`PoError`] found in a file
+pub struct PoErrors(pub Vec<PoError>);
+
+impl fmt::Display for PoErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "found {} error(s) in the po file:", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PoErrors {}
+
+#[derive(Debug, Error)]
+/// An error that happened while reading a po file with a [`PoReader`]
+pub enum PoReadError {
+    #[error("can't read the po file")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parse(#[from] PoErrors),
+}
+
+/// A single entry of a po file, with all the metadata gettext define.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PoEntry {
+    /// The line (starting at 1) this entry start at
+    pub line: usize,
+    /// The translator comments (lines starting with ``# ``)
+    pub translator_comments: Vec<String>,
+    /// The extracted comments (lines starting with ``#.``)
+    pub extracted_comments: Vec<String>,
+    /// The line of each element of [`PoEntry::extracted_comments`]
+    pub extracted_comments_line: Vec<usize>,
+    /// The references (lines starting with ``#:``), one element per reference
+    pub references: Vec<String>,
+    /// The line each reference was found at. May be empty if the entry wasn't parsed.
+    pub references_line: Vec<usize>,
+    /// The flags (lines starting with ``#,``), like ``fuzzy``
+    pub flags: Vec<String>,
+    /// The ``#| msgctxt`` of the previous version of this entry
+    pub previous_msgctxt: Option<String>,
+    /// The ``#| msgid`` of the previous version of this entry
+    pub previous_msgid: Option<String>,
+    /// The ``#| msgid_plural`` of the previous version of this entry
+    pub previous_msgid_plural: Option<String>,
+    pub msgctxt: Option<String>,
+    pub msgid: String,
+    pub msgid_plural: Option<String>,
+    /// The translations. There is only one for entries without plural (``msgstr``),
+    /// otherwise ``msgstr[n]`` is at index n.
+    pub msgstr: Vec<String>,
+    /// The line the element of [`PoEntry::msgstr`] with the same index start at. May be empty if the entry
+    /// wasn't parsed.
+    pub msgstr_line: Vec<usize>,
+    /// true if this entry is commented out with ``#~``
+    pub obsolete: bool,
+}
+
+impl PoEntry {
+    /// Return true if this entry has the given flag (e.g. ``fuzzy``)
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|x| x == flag)
+    }
+
+    /// Return the (first) translation, or None if it isn't translated
+    pub fn translation(&self) -> Option<&str> {
+        match self.msgstr.first() {
+            Some(msgstr) if !msgstr.is_empty() => Some(msgstr),
+            _ => None,
+        }
+    }
+
+    /// Return true if this is the header entry (the one with an empty msgid and no location)
+    pub fn is_header(&self) -> bool {
+        self.msgid.is_empty() && self.extracted_comments.is_empty() && self.references.is_empty()
+    }
+
+    /// Iterate over the references, with the line they were found at (or the line of the entry if unknown)
+    pub fn references_with_line(&self) -> impl Iterator<Item = (&String, usize)> {
+        self.references
+            .iter()
+            .enumerate()
+            .map(move |(index, reference)| {
+                (
+                    reference,
+                    self.references_line
+                        .get(index)
+                        .copied()
+                        .unwrap_or(self.line),
+                )
+            })
+    }
+
+    /// The line the ``msgstr`` (or ``msgstr[index]``) start at, or the line of the entry if unknown
+    pub fn msgstr_line(&self, index: usize) -> usize {
+        self.msgstr_line.get(index).copied().unwrap_or(self.line)
+    }
+
+    /// Iterate over the extracted comments, with the line they were found at
+    /// (or the line of the entry if unknown)
+    pub fn locations_comment(&self) -> impl Iterator<Item = (&String, usize)> {
+        self.extracted_comments
+            .iter()
+            .enumerate()
+            .map(move |(index, comment)| {
+                (
+                    comment,
+                    self.extracted_comments_line
+                        .get(index)
+                        .copied()
+                        .unwrap_or(self.line),
+                )
+            })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Msgctxt,
+    Msgid,
+    MsgidPlural,
+    Msgstr(usize),
+    PreviousMsgctxt,
+    PreviousMsgid,
+    PreviousMsgidPlural,
+}
+
+/// A line-by-line po parser. Feed it with [`PoParser::parse_line`], and then call [`PoParser::finish`]
+/// to get the last entry.
+#[derive(Default)]
+pub struct PoParser {
+    current: PoEntry,
+    /// true if something has been put in current
+    started: bool,
+    /// true once a msgstr has been read. The next comment or msgid will start a new entry.
+    has_msgstr: bool,
+    field: Option<Field>,
+    warnings: Vec<PoWarning>,
+    errors: Vec<PoError>,
+}
+
+impl PoParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a single line (without the line return), returning an entry if this line is the start of a new one.
+    /// ``line_nb`` is the number of the line, starting at 1.
+    pub fn parse_line(&mut self, line_nb: usize, raw_line: &str) -> Option<PoEntry> {
+        let mut finished = None;
+        let mut line = raw_line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            if self.has_msgstr {
+                finished = self.take_entry();
+            }
+            return finished;
+        }
+
+        let mut obsolete = false;
+        if let Some(rest) = line.strip_prefix("#~") {
+            obsolete = true;
+            line = rest.trim_start();
+        };
+
+        if line.starts_with('"') {
+            if let Some(field) = self.field {
+                let text = self.unescape(line_nb, raw_line);
+                self.field_mut(field).push_str(&text);
+            } else {
+                self.warnings
+                    .push(PoWarning::LineTypeUnknown(line_nb, "\"".to_string()));
+            }
+            return finished;
+        }
+
+        let previous = match line.strip_prefix("#|") {
+            Some(rest) => Some(rest),
+            // obsolete previous fields are written ``#~| msgid``
+            None if obsolete => line.strip_prefix('|'),
+            None => None,
+        };
+        if let Some(rest) = previous {
+            let rest = rest.trim_start();
+            if rest.starts_with('"') {
+                // continuation of a previous field
+                match self.field {
+                    Some(field @ Field::PreviousMsgctxt)
+                    | Some(field @ Field::PreviousMsgid)
+                    | Some(field @ Field::PreviousMsgidPlural) => {
+                        let text = self.unescape(line_nb, raw_line);
+                        self.field_mut(field).push_str(&text);
+                    }
+                    _ => self
+                        .warnings
+                        .push(PoWarning::LineTypeUnknown(line_nb, "#| \"".to_string())),
+                };
+                return finished;
+            };
+            let (keyword, _) = split_keyword(rest);
+            let field = match keyword {
+                "msgctxt" => Field::PreviousMsgctxt,
+                "msgid" => Field::PreviousMsgid,
+                "msgid_plural" => Field::PreviousMsgidPlural,
+                other => {
+                    self.warnings
+                        .push(PoWarning::LineTypeUnknown(line_nb, format!("#| {}", other)));
+                    return finished;
+                }
+            };
+            if self.has_msgstr {
+                finished = self.take_entry();
+            };
+            self.start(line_nb, obsolete);
+            self.field = Some(field);
+            let text = self.unescape(line_nb, raw_line);
+            *self.field_mut(field) = text;
+            return finished;
+        };
+
+        let (keyword, rest) = if let Some(comment) = line.strip_prefix('#') {
+            match comment.chars().next() {
+                Some('.') | Some(':') | Some(',') => (&line[..2], &line[2..]),
+                _ => ("#", comment),
+            }
+        } else {
+            split_keyword(line)
+        };
+
+        let field = match keyword {
+            "#" | "#." | "#:" | "#," => {
+                if self.has_msgstr {
+                    finished = self.take_entry();
+                };
+                self.start(line_nb, obsolete);
+                self.field = None;
+                let rest = rest.strip_prefix(' ').unwrap_or(rest);
+                match keyword {
+                    "#" => self.current.translator_comments.push(rest.to_string()),
+                    "#." => {
+                        self.current.extracted_comments.push(rest.to_string());
+                        self.current.extracted_comments_line.push(line_nb);
+                    }
+                    "#:" => {
+                        for reference in rest.split_whitespace() {
+                            self.current.references.push(reference.to_string());
+                            self.current.references_line.push(line_nb);
+                        }
+                    }
+                    _ => self.current.flags.extend(
+                        rest.split(',')
+                            .map(|x| x.trim())
+                            .filter(|x| !x.is_empty())
+                            .map(|x| x.to_string()),
+                    ),
+                };
+                return finished;
+            }
+            "msgctxt" => Field::Msgctxt,
+            "msgid" => Field::Msgid,
+            "msgid_plural" => Field::MsgidPlural,
+            "msgstr" => Field::Msgstr(0),
+            other => {
+                if let Some(index) = other
+                    .strip_prefix("msgstr[")
+                    .and_then(|x| x.strip_suffix(']'))
+                    .and_then(|x| x.parse::<usize>().ok())
+                {
+                    Field::Msgstr(index)
+                } else {
+                    self.warnings
+                        .push(PoWarning::LineTypeUnknown(line_nb, other.to_string()));
+                    return finished;
+                }
+            }
+        };
+
+        match field {
+            Field::Msgstr(_) => self.has_msgstr = true,
+            _ => {
+                if self.has_msgstr {
+                    finished = self.take_entry();
+                }
+            }
+        };
+        self.start(line_nb, obsolete);
+        if let Field::Msgstr(index) = field {
+            let msgstr_line = &mut self.current.msgstr_line;
+            if msgstr_line.len() <= index {
+                msgstr_line.resize(index + 1, line_nb);
+            };
+            msgstr_line[index] = line_nb;
+        };
+        self.field = Some(field);
+        let text = self.unescape(line_nb, raw_line);
+        *self.field_mut(field) = text;
+
+        finished
+    }
+
+    /// Return the last entry of the file, if any. Should be called once every line has been parsed.
+    pub fn finish(&mut self) -> Option<PoEntry> {
+        self.take_entry()
+    }
+
+    /// Return the warnings encountered since the last call to this function
+    pub fn take_warnings(&mut self) -> Vec<PoWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Return the errors encountered since the last call to this function
+    pub fn take_errors(&mut self) -> Vec<PoError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn start(&mut self, line_nb: usize, obsolete: bool) {
+        if !self.started {
+            self.started = true;
+            self.current.line = line_nb;
+        }
+        if obsolete {
+            self.current.obsolete = true;
+        }
+    }
+
+    fn take_entry(&mut self) -> Option<PoEntry> {
+        let started = self.started;
+        self.started = false;
+        self.has_msgstr = false;
+        self.field = None;
+        let entry = std::mem::take(&mut self.current);
+        if started {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    fn field_mut(&mut self, field: Field) -> &mut String {
+        let entry = &mut self.current;
+        match field {
+            Field::Msgctxt => entry.msgctxt.get_or_insert_with(String::new),
+            Field::Msgid => &mut entry.msgid,
+            Field::MsgidPlural => entry.msgid_plural.get_or_insert_with(String::new),
+            Field::Msgstr(index) => {
+                if entry.msgstr.len() <= index {
+                    entry.msgstr.resize(index + 1, String::new());
+                };
+                &mut entry.msgstr[index]
+            }
+            Field::PreviousMsgctxt => entry.previous_msgctxt.get_or_insert_with(String::new),
+            Field::PreviousMsgid => entry.previous_msgid.get_or_insert_with(String::new),
+            Field::PreviousMsgidPlural => {
+                entry.previous_msgid_plural.get_or_insert_with(String::new)
+            }
+        }
+    }
+
+    fn unescape(&mut self, line_nb: usize, line: &str) -> String {
+        match unescape_gettext_string(line_nb, line) {
+            Ok((text, warning)) => {
+                if let Some(warning) = warning {
+                    self.warnings.push(warning);
+                };
+                text
+            }
+            Err(err) => {
+                self.errors.push(err);
+                String::new()
+            }
+        }
+    }
+}
+
+/// Split the first word of the line from the rest of the line
+fn split_keyword(line: &str) -> (&str, &str) {
+    match line.find(|c: char| c.is_whitespace() || c == '"') {
+        Some(pos) => (&line[..pos], &line[pos..]),
+        None => (line, ""),
+    }
+}
+
+/// Write the entry in the po format, followed by an empty line
+pub fn write_po_entry(entry: &PoEntry, result: &mut String) {
+    let (prefix, previous_prefix) = if entry.obsolete {
+        ("#~ ", "#~| ")
+    } else {
+        ("", "#| ")
+    };
+    for comment in &entry.translator_comments {
+        if comment.is_empty() {
+            result.push_str("#\n");
+        } else {
+            result.push_str(&format!("# {}\n", comment));
+        }
+    }
+    for comment in &entry.extracted_comments {
+        result.push_str(&format!("#. {}\n", comment));
+    }
+    for reference in &entry.references {
+        result.push_str(&format!("#: {}\n", reference));
+    }
+    if !entry.flags.is_empty() {
+        result.push_str(&format!("#, {}\n", entry.flags.join(", ")));
+    }
+    let mut push_field = |prefix: &str, keyword: &str, text: &str| {
+        result.push_str(&format!(
+            "{}{} {}\n",
+            prefix,
+            keyword,
+            escape_string_for_gettext(text)
+        ));
+    };
+    if let Some(previous_msgctxt) = &entry.previous_msgctxt {
+        push_field(previous_prefix, "msgctxt", previous_msgctxt);
+    }
+    if let Some(previous_msgid) = &entry.previous_msgid {
+        push_field(previous_prefix, "msgid", previous_msgid);
+    }
+    if let Some(previous_msgid_plural) = &entry.previous_msgid_plural {
+        push_field(previous_prefix, "msgid_plural", previous_msgid_plural);
+    }
+    if let Some(msgctxt) = &entry.msgctxt {
+        push_field(prefix, "msgctxt", msgctxt);
+    }
+    push_field(prefix, "msgid", &entry.msgid);
+    if let Some(msgid_plural) = &entry.msgid_plural {
+        push_field(prefix, "msgid_plural", msgid_plural);
+        for (index, msgstr) in entry.msgstr.iter().enumerate() {
+            push_field(prefix, &format!("msgstr[{}]", index), msgstr);
+        }
+    } else {
+        push_field(
+            prefix,
+            "msgstr",
+            entry.msgstr.first().map(|x| x.as_str()).unwrap_or(""),
+        );
+    }
+    result.push('\n');
+}
+
+/// Write all the entries in the po format
+pub fn write_po(entries: &[PoEntry]) -> String {
+    let mut result = String::new();
+    for entry in entries {
+        write_po_entry(entry, &mut result);
+    }
+    result
+}
+
+/// Write the entries in the po format to ``writer``, one at a time
+pub fn write_po_to<'a, W: Write + ?Sized>(
+    entries: impl IntoIterator<Item = &'a PoEntry>,
+    writer: &mut W,
+) -> io::Result<()> {
+    let mut buffer = String::new();
+    for entry in entries {
+        buffer.clear();
+        write_po_entry(entry, &mut buffer);
+        writer.write_all(buffer.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Read the entries of a po file one at a time, without loading the whole file in memory.
+/// The warnings and errors encountered are kept until they are taken with [`PoReader::take_warnings`] and
+/// [`PoReader::take_errors`].
+pub struct PoReader<R> {
+    lines: io::Lines<R>,
+    line_nb: usize,
+    parser: PoParser,
+    finished: bool,
+}
+
+impl<R: BufRead> PoReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            line_nb: 0,
+            parser: PoParser::new(),
+            finished: false,
+        }
+    }
+
+    /// Return the warnings encountered since the last call to this function
+    pub fn take_warnings(&mut self) -> Vec<PoWarning> {
+        self.parser.take_warnings()
+    }
+
+    /// Return the errors encountered since the last call to this function
+    pub fn take_errors(&mut self) -> Vec<PoError> {
+        self.parser.take_errors()
+    }
+}
+
+impl<R: BufRead> Iterator for PoReader<R> {
+    type Item = io::Result<PoEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            match self.lines.next() {
+                Some(Ok(line)) => {
+                    self.line_nb += 1;
+                    if let Some(entry) = self.parser.parse_line(self.line_nb, &line) {
+                        return Some(Ok(entry));
+                    }
+                }
+                Some(Err(err)) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+                None => {
+                    self.finished = true;
+                    return self.parser.finish().map(Ok);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Parse all the entries of a po file, returning them with the warnings and errors encountered.
+pub fn parse_po(file: &str) -> (Vec<PoEntry>, Vec<PoWarning>, Vec<PoError>) {
+    let mut parser = PoParser::new();
+    let mut entries = Vec::new();
+    for (line_nb, line) in file.lines().enumerate() {
+        if let Some(entry) = parser.parse_line(line_nb + 1, line) {
+            entries.push(entry);
+        }
+    }
+    if let Some(last) = parser.finish() {
+        entries.push(last);
+    }
+    (entries, parser.take_warnings(), parser.take_errors())
+}
+
+/// Decode the quoted string(s) contained in this line, as escaped by [`crate::escape_string_for_gettext`].
+/// Text outside of quote is ignored.
+pub fn unescape_gettext_string(
+    line_nb: usize,
+    line: &str,
+) -> Result<(String, Option<PoWarning>), PoError> {
+    let mut result = String::new();
+    let mut inside_quote = false;
+    #[derive(PartialEq)]
+    enum Escape {
+        None,
+        NextEscaped,
+        SecondEscaped,
+        WillBeEscaped,
+        ReadingNumber,
+    }
+    let mut escape = Escape::None;
+    let mut number_being_read = String::new();
+    // the column of the first \ of the current hex escape
+    let mut escape_start = 0;
+
+    for (column, ch) in line.chars().enumerate() {
+        let column = column + 1;
+        if escape == Escape::SecondEscaped {
+            if ch == 'x' {
+                escape = Escape::WillBeEscaped;
+                continue;
+            } else if ch == 'r' {
+                escape = Escape::None;
+                result.push('\r');
+                continue;
+            } else {
+                escape = Escape::None;
+                result.push('\\');
+            };
+        }
+
+        if escape == Escape::NextEscaped {
+            escape = Escape::None;
+            match ch {
+                'n' => result.push('\n'),
+                'r' => result.push('\r'),
+                '\\' => escape = Escape::SecondEscaped,
+                ch => result.push(ch),
+            }
+        } else if escape == Escape::WillBeEscaped {
+            if ch == '{' {
+                escape = Escape::ReadingNumber;
+            } else {
+                return Err(PoError::InvalidHexEscape(
+                    line_nb,
+                    escape_start,
+                    format!("\\\\x{}", ch),
+                ));
+            }
+        } else if escape == Escape::ReadingNumber {
+            if ch == '}' {
+                let new_ch_text = u32::from_str_radix(&number_being_read, 16).map_err(|err| {
+                    PoError::InvalidHexNumber(line_nb, escape_start, number_being_read.clone(), err)
+                })?;
+                let new_ch = char::from_u32(new_ch_text).ok_or_else(|| {
+                    PoError::InvalidCharCode(line_nb, escape_start, number_being_read.clone())
+                })?;
+                result.push(new_ch);
+                escape = Escape::None;
+                number_being_read = String::new();
+            } else {
+                number_being_read.push(ch);
+            }
+        } else if ch == '"' {
+            inside_quote = !inside_quote;
+        } else if inside_quote && ch == '\\' {
+            escape = Escape::NextEscaped;
+            escape_start = column;
+        } else if inside_quote {
+            result.push(ch);
+        }
+    }
+
+    let warning = match escape {
+        Escape::SecondEscaped => {
+            result.push('\\');
+            None
+        }
+        Escape::None if inside_quote => Some(PoWarning::UnclosedQuote(line_nb)),
+        Escape::None => None,
+        Escape::ReadingNumber => {
+            return Err(PoError::UnclosedHexEscape(
+                line_nb,
+                escape_start,
+                format!("\\\\x{{{}", number_being_read),
+            ))
+        }
+        _ => Some(PoWarning::UnfinishedEscape(line_nb)),
+    };
+
+    Ok((result, warning))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_reverses_escape() {
+        for text in [
+            "line\r\nbreak",
+            "\r",
+            "new\nline",
+            "\"quoted\"",
+            "back\\slash",
+            "bell\u{7} and\u{1b}escape",
+        ] {
+            let escaped = crate::escape_string_for_gettext(text);
+            let (unescaped, warning) = unescape_gettext_string(1, &escaped).unwrap();
+            assert_eq!(unescaped, text, "escaped as {}", escaped);
+            assert!(warning.is_none());
+        }
+    }
+
+    /// Parse a file that should be valid, without warning
+    fn parse_valid(file: &str) -> Vec<PoEntry> {
+        let (entries, warnings, errors) = parse_po(file);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert!(errors.is_empty(), "{:?}", errors);
+        entries
+    }
+
+    #[test]
+    fn parse_header() {
+        let entries = parse_valid(
+            "msgid \"\"\nmsgstr \"\"\n\"Language: fr\\n\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n",
+        );
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].is_header());
+        assert_eq!(
+            entries[0].msgstr,
+            vec!["Language: fr\nContent-Type: text/plain; charset=UTF-8\n".to_string()]
+        );
+        assert_eq!(entries[0].msgstr_line, vec![2]);
+    }
+
+    #[test]
+    fn parse_context_and_multi_line_strings() {
+        let entries = parse_valid(concat!(
+            "msgctxt \"script.bin 100\"\n",
+            "msgid \"\"\n",
+            "\"first \"\n",
+            "\"second\"\n",
+            "msgstr \"premier \"\n",
+            "\"second\"\n",
+            "msgid \"next\"\n",
+            "msgstr \"\"\n",
+        ));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].msgctxt.as_deref(), Some("script.bin 100"));
+        assert_eq!(entries[0].msgid, "first second");
+        assert_eq!(entries[0].translation(), Some("premier second"));
+        assert_eq!(entries[0].line, 1);
+        assert_eq!(entries[0].msgstr_line(0), 5);
+        // a msgid after a msgstr start a new entry, even without an empty line
+        assert_eq!(entries[1].msgctxt, None);
+        assert_eq!(entries[1].msgid, "next");
+        assert_eq!(entries[1].translation(), None);
+        assert_eq!(entries[1].line, 7);
+    }
+
+    #[test]
+    fn parse_comments_flags_and_references() {
+        let entries = parse_valid(concat!(
+            "# translator comment\n",
+            "#\n",
+            "#. extracted comment\n",
+            "#: a.bin:0x00000001 b.bin:0x00000002\n",
+            "#: c.bin:0x00000003\n",
+            "#, fuzzy, pmd-unk:a.bin:0x00000001=5\n",
+            "msgid \"Yes\"\n",
+            "msgstr \"Oui\"\n",
+        ));
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.translator_comments, vec!["translator comment", ""]);
+        assert_eq!(entry.extracted_comments, vec!["extracted comment"]);
+        assert_eq!(entry.extracted_comments_line, vec![3]);
+        assert_eq!(
+            entry.references,
+            vec!["a.bin:0x00000001", "b.bin:0x00000002", "c.bin:0x00000003"]
+        );
+        assert_eq!(entry.references_line, vec![4, 4, 5]);
+        assert_eq!(entry.flags, vec!["fuzzy", "pmd-unk:a.bin:0x00000001=5"]);
+        assert!(entry.has_flag("fuzzy"));
+        assert!(!entry.is_header());
+    }
+
+    #[test]
+    fn parse_previous_fields() {
+        let entries = parse_valid(concat!(
+            "#, fuzzy\n",
+            "#| msgctxt \"old context\"\n",
+            "#| msgid \"old \"\n",
+            "#| \"text\"\n",
+            "#| msgid_plural \"old texts\"\n",
+            "msgid \"new text\"\n",
+            "msgstr \"nouveau texte\"\n",
+        ));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].previous_msgctxt.as_deref(), Some("old context"));
+        assert_eq!(entries[0].previous_msgid.as_deref(), Some("old text"));
+        assert_eq!(
+            entries[0].previous_msgid_plural.as_deref(),
+            Some("old texts")
+        );
+        assert_eq!(entries[0].msgid, "new text");
+        assert!(!entries[0].obsolete);
+    }
+
+    #[test]
+    fn parse_obsolete_entries() {
+        let entries = parse_valid(concat!(
+            "msgid \"kept\"\n",
+            "msgstr \"gardé\"\n",
+            "\n",
+            "#~| msgid \"older\"\n",
+            "#~ msgid \"gone\"\n",
+            "#~ \"away\"\n",
+            "#~ msgstr \"parti\"\n",
+        ));
+        assert_eq!(entries.len(), 2);
+        assert!(!entries[0].obsolete);
+        assert!(entries[1].obsolete);
+        assert_eq!(entries[1].previous_msgid.as_deref(), Some("older"));
+        assert_eq!(entries[1].msgid, "goneaway");
+        assert_eq!(entries[1].translation(), Some("parti"));
+        assert_eq!(entries[1].line, 4);
+    }
+
+    #[test]
+    fn parse_plurals() {
+        let entries = parse_valid(concat!(
+            "msgid \"one apple\"\n",
+            "msgid_plural \"{} apples\"\n",
+            "msgstr[0] \"une pomme\"\n",
+            "msgstr[1] \"{} pommes\"\n",
+        ));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].msgid_plural.as_deref(), Some("{} apples"));
+        assert_eq!(entries[0].msgstr, vec!["une pomme", "{} pommes"]);
+        assert_eq!(entries[0].msgstr_line, vec![3, 4]);
+    }
+
+    #[test]
+    fn written_entries_are_parsed_back() {
+        let entry = PoEntry {
+            line: 1,
+            translator_comments: vec!["comment".to_string()],
+            extracted_comments: vec!["extracted".to_string()],
+            extracted_comments_line: vec![2],
+            references: vec!["a.bin:0x00000001".to_string()],
+            references_line: vec![3],
+            flags: vec!["fuzzy".to_string()],
+            previous_msgid: Some("old\nline".to_string()),
+            msgctxt: Some("context".to_string()),
+            msgid: "one \"line\"".to_string(),
+            msgid_plural: Some("lines".to_string()),
+            msgstr: vec!["une ligne".to_string(), "des lignes".to_string()],
+            msgstr_line: vec![9, 10],
+            ..Default::default()
+        };
+        let mut obsolete = entry.clone();
+        obsolete.obsolete = true;
+        obsolete.line = 12;
+        obsolete.extracted_comments_line = vec![13];
+        obsolete.references_line = vec![14];
+        obsolete.msgstr_line = vec![20, 21];
+        assert_eq!(
+            parse_valid(&write_po(&[entry.clone(), obsolete.clone()])),
+            vec![entry, obsolete]
+        );
+    }
+
+    #[test]
+    fn errors_are_located() {
+        let (_, _, errors) = parse_po("msgid \"ok\"\nmsgstr \"a\\\\x{zz}\"\n");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            PoError::InvalidHexNumber(2, 10, number, _) if number == "zz"
+        ));
+
+        let (_, _, errors) = parse_po("msgid \"\\\\x{41\"\nmsgstr \"\"\n");
+        assert!(matches!(&errors[0], PoError::UnclosedHexEscape(1, 8, _)));
+        assert_eq!(errors[0].line(), 1);
+
+        let (_, warnings, errors) = parse_po("msgid \"unclosed\nmsgstr \"\"\nunknown \"\"\n");
+        assert!(errors.is_empty());
+        assert!(matches!(warnings[0], PoWarning::UnclosedQuote(1)));
+        assert!(
+            matches!(&warnings[1], PoWarning::LineTypeUnknown(3, keyword) if keyword == "unknown")
+        );
+    }
+
+    #[test]
+    fn streamed_file_is_parsed_identically() {
+        let file = concat!(
+            "msgid \"\"\n",
+            "msgstr \"Language: fr\\n\"\n",
+            "\n",
+            "# translator comment\r\n",
+            "#: a.bin:0x00000001\r\n",
+            "msgid \"first \"\n",
+            "\"line\"\n",
+            "msgstr \"unclosed\n",
+            "unknown \"\"\n",
+            "\n",
+            "msgid \"escape\"\n",
+            "msgstr \"a\\\\x{zz}\"\n",
+            "\n",
+            "#~ msgid \"obsolete\"\n",
+            "#~ msgstr \"without end of line\"",
+        );
+        let (entries, warnings, errors) = parse_po(file);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(errors.len(), 1);
+
+        let readers: Vec<Box<dyn BufRead>> = vec![
+            Box::new(file.as_bytes()),
+            // the content arrive in small parts, like from the standard input
+            Box::new(io::BufReader::with_capacity(3, file.as_bytes())),
+        ];
+        for reader in readers {
+            let mut po_reader = PoReader::new(reader);
+            let streamed_entries = po_reader.by_ref().collect::<io::Result<Vec<_>>>().unwrap();
+            assert_eq!(streamed_entries, entries);
+            assert_eq!(
+                format!("{:?}", po_reader.take_warnings()),
+                format!("{:?}", warnings)
+            );
+            assert_eq!(
+                format!("{:?}", po_reader.take_errors()),
+                format!("{:?}", errors)
+            );
+        }
+    }
+}
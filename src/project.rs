@@ -0,0 +1,297 @@
+use crate::{AmbiguityCriteria, FuzzyPolicy, PoStorageMode};
+use serde::Deserialize;
+use std::{
+    fs::read_to_string,
+    io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// The name of the project file, searched in the current directory
+pub const PROJECT_FILE_NAME: &str = "pmdtranslate.toml";
+
+#[derive(Debug, Error)]
+pub enum ProjectError {
+    #[error("can't read the project file at {0:?}")]
+    Io(PathBuf, #[source] io::Error),
+    #[error("can't parse the project file at {0:?}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("the project doesn't have a {0:?} language")]
+    UnknownLanguage(String),
+}
+
+/// How the messages are stored in the game
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameFormat {
+    /// A message farc, like ``message_us.bin``, with its ``.lst`` list file next to it
+    #[default]
+    Farc,
+    /// A folder of message files
+    Folder,
+}
+
+/// The ``[game]`` section: the original files of the game
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GameConfig {
+    #[serde(default)]
+    pub format: GameFormat,
+    /// The message farc or folder
+    pub messages: PathBuf,
+    /// The ``code_table.bin`` file
+    pub code_table: PathBuf,
+    /// The Project-Id-Version of the template header. Default to the game and the region of the messages.
+    pub project_id_version: Option<String>,
+    /// A file containing every character the game can display
+    pub charset: Option<PathBuf>,
+}
+
+/// The ``[po]`` section: how the po files are laid out
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoConfig {
+    pub storage: PoStorageMode,
+    /// The extracted pot file or folder
+    pub template: PathBuf,
+    /// The strings that are translated separately at each location
+    #[serde(default)]
+    pub discriminators: Vec<String>,
+    /// A file of discriminator rules, used after ``discriminators``
+    pub discriminator_file: Option<PathBuf>,
+    /// The criteria of the shared strings that may be ambiguous, like ``{ single_word = true }``
+    #[serde(default)]
+    pub ambiguity: AmbiguityCriteria,
+    /// Where to write the review list of the ambiguous strings
+    pub ambiguity_review: Option<PathBuf>,
+    /// Translate the ambiguous strings separately, without review
+    #[serde(default)]
+    pub split_ambiguous: bool,
+    /// The grouping rules of the po folders, like ``"script_m01* = chapter_1"``
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// A file containing more grouping rules
+    pub grouping_file: Option<PathBuf>,
+    /// The maximal number of entries of a po file of a folder
+    pub max_entries: Option<usize>,
+    /// Remove the po files of a folder that aren't part of the grouping when it is written
+    #[serde(default)]
+    pub prune: bool,
+}
+
+/// A ``[[language]]`` section: a translation, and where it is built
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LanguageConfig {
+    /// The language code, like ``fr``
+    pub code: String,
+    /// The translated po file or folder
+    pub po: PathBuf,
+    /// The built message farc or folder
+    pub output: PathBuf,
+    /// Keep the files and messages of the game that aren't in the translation
+    #[serde(default)]
+    pub base: bool,
+    #[serde(default = "default_fuzzy_policy")]
+    pub fuzzy: FuzzyPolicy,
+}
+
+fn default_fuzzy_policy() -> FuzzyPolicy {
+    FuzzyPolicy::Use
+}
+
+/// A ``pmdtranslate.toml`` project file, describing the game files, the po files and the translations.
+/// Relative paths are relative to the directory of the project file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    pub game: GameConfig,
+    pub po: PoConfig,
+    #[serde(default, rename = "language")]
+    pub languages: Vec<LanguageConfig>,
+}
+
+impl Project {
+    /// Parse the content of a project file, whose relative paths are relative to ``root``
+    pub fn parse(content: &str, root: &Path) -> Result<Self, toml::de::Error> {
+        let mut project: Self = toml::from_str(content)?;
+        let paths = vec![
+            &mut project.game.messages,
+            &mut project.game.code_table,
+            &mut project.po.template,
+        ];
+        for path in paths
+            .into_iter()
+            .chain(project.game.charset.as_mut())
+            .chain(project.po.grouping_file.as_mut())
+            .chain(project.po.discriminator_file.as_mut())
+            .chain(project.po.ambiguity_review.as_mut())
+        {
+            *path = root.join(&*path);
+        }
+        for language in &mut project.languages {
+            language.po = root.join(&language.po);
+            language.output = root.join(&language.output);
+        }
+        Ok(project)
+    }
+
+    /// Load a project file
+    pub fn load(path: &Path) -> Result<Self, ProjectError> {
+        let content =
+            read_to_string(path).map_err(|err| ProjectError::Io(path.to_path_buf(), err))?;
+        let root = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&content, root).map_err(|err| ProjectError::Parse(path.to_path_buf(), err))
+    }
+
+    /// Return the languages with the given code, or every language if ``code`` is ``None``
+    pub fn select_languages(
+        &self,
+        code: Option<&str>,
+    ) -> Result<Vec<&LanguageConfig>, ProjectError> {
+        match code {
+            None => Ok(self.languages.iter().collect()),
+            Some(code) => self
+                .languages
+                .iter()
+                .find(|language| language.code == code)
+                .map(|language| vec![language])
+                .ok_or_else(|| ProjectError::UnknownLanguage(code.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    const PROJECT: &str = r#"
+[game]
+messages = "game/message_us.bin"
+code_table = "/games/psmd/code_table.bin"
+charset = "charset.txt"
+
+[po]
+storage = "folder"
+template = "pot"
+grouping_file = "groups.txt"
+ambiguity = { single_word = true }
+
+[[language]]
+code = "fr"
+po = "fr"
+output = "build/message_fr.bin"
+base = true
+
+[[language]]
+code = "de"
+po = "de"
+output = "build/message_de.bin"
+fuzzy = "fail"
+"#;
+
+    #[test]
+    fn paths_are_relative_to_the_project() {
+        let root = Path::new("translation");
+        let project = Project::parse(PROJECT, root).unwrap();
+        assert_eq!(project.game.messages, root.join("game/message_us.bin"));
+        assert_eq!(
+            project.game.code_table,
+            Path::new("/games/psmd/code_table.bin")
+        );
+        assert_eq!(project.game.charset, Some(root.join("charset.txt")));
+        assert_eq!(project.po.template, root.join("pot"));
+        assert_eq!(project.po.grouping_file, Some(root.join("groups.txt")));
+        assert_eq!(project.po.discriminator_file, None);
+        assert_eq!(project.languages[0].po, root.join("fr"));
+        assert_eq!(
+            project.languages[1].output,
+            root.join("build/message_de.bin")
+        );
+    }
+
+    #[test]
+    fn missing_optional_keys_have_a_default() {
+        let project = Project::parse(PROJECT, Path::new("")).unwrap();
+        assert_eq!(project.game.format, GameFormat::Farc);
+        assert_eq!(project.po.storage, PoStorageMode::Folder);
+        assert!(project.po.discriminators.is_empty());
+        assert!(project.po.ambiguity.single_word);
+        assert!(!project.po.split_ambiguous);
+        assert!(!project.po.prune);
+        assert_eq!(project.po.max_entries, None);
+        assert!(project.languages[0].base);
+        assert_eq!(project.languages[0].fuzzy, FuzzyPolicy::Use);
+        assert!(!project.languages[1].base);
+        assert_eq!(project.languages[1].fuzzy, FuzzyPolicy::Fail);
+    }
+
+    #[test]
+    fn languages_are_selected_by_code() {
+        let project = Project::parse(PROJECT, Path::new("")).unwrap();
+        let codes = |languages: Vec<&LanguageConfig>| -> Vec<String> {
+            languages
+                .into_iter()
+                .map(|language| language.code.clone())
+                .collect()
+        };
+        assert_eq!(codes(project.select_languages(None).unwrap()), ["fr", "de"]);
+        assert_eq!(codes(project.select_languages(Some("de")).unwrap()), ["de"]);
+        assert!(matches!(
+            project.select_languages(Some("es")),
+            Err(ProjectError::UnknownLanguage(code)) if code == "es"
+        ));
+    }
+
+    #[test]
+    fn unknown_and_missing_keys_are_refused() {
+        for (content, message) in [
+            (
+                PROJECT.replace("charset =", "charst ="),
+                "unknown field `charst`",
+            ),
+            (
+                PROJECT.replace("base = true", "bse = true"),
+                "unknown field `bse`",
+            ),
+            (
+                format!("{}\n[output]\npath = \"build\"\n", PROJECT),
+                "unknown field `output`",
+            ),
+            (
+                PROJECT.replace("code_table =", "# code_table ="),
+                "missing field `code_table`",
+            ),
+            (PROJECT.replace("code = \"fr\"", ""), "missing field `code`"),
+            (
+                PROJECT.replace("storage = \"folder\"", "storage = \"zip\""),
+                "unknown variant `zip`",
+            ),
+        ] {
+            let error = Project::parse(&content, Path::new("")).unwrap_err();
+            assert!(
+                error.to_string().contains(message),
+                "{:?} doesn't contain {:?}",
+                error.to_string(),
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn loaded_paths_are_relative_to_the_project_file() {
+        let folder = env::temp_dir().join(format!("pmdtranslate-project-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join(PROJECT_FILE_NAME);
+        fs::write(&path, PROJECT).unwrap();
+        let project = Project::load(&path);
+        fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(project.unwrap().po.template, folder.join("pot"));
+
+        assert!(matches!(
+            Project::load(&path),
+            Err(ProjectError::Io(error_path, _)) if error_path == path
+        ));
+    }
+}
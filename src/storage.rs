@@ -0,0 +1,504 @@
+use crate::{
+    pipeline::{io_error, is_plain_file_name},
+    po_entry_locations, write_po_to, Catalog, FuzzyPolicy, GettextWriter, PipelineError, PoEntry,
+    PoErrors, PoGrouping, PoReadError, PoReader, PoWarning,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    ffi::OsStr,
+    fs::{create_dir_all, read_dir, remove_file, File},
+    io::{self, stdin, stdout, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The path of a po file that mean the standard input (when reading) or output (when writing)
+pub const STANDARD_STREAM: &str = "-";
+
+/// How the po files are stored
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PoStorageMode {
+    /// A single po file
+    File,
+    /// A folder with one po file per message file
+    Folder,
+}
+
+impl FromStr for PoStorageMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, &'static str> {
+        match s {
+            "file" => Ok(PoStorageMode::File),
+            "folder" => Ok(PoStorageMode::Folder),
+            _ => Err("The mod should be either 'file' or 'folder'"),
+        }
+    }
+}
+
+/// The entries of each po file, with the path of the file
+type PoFiles = Vec<(PathBuf, Vec<PoEntry>)>;
+
+/// Open a po file for reading, or the standard input for [`STANDARD_STREAM`]
+fn open_po_file(path: &Path) -> Result<Box<dyn BufRead>, PipelineError> {
+    if path == Path::new(STANDARD_STREAM) {
+        Ok(Box::new(stdin().lock()))
+    } else {
+        Ok(Box::new(BufReader::new(
+            File::open(path).map_err(io_error(path))?,
+        )))
+    }
+}
+
+/// Write a po file with the given function, or write to the standard output for [`STANDARD_STREAM`]
+fn write_po_file(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<(), PipelineError> {
+    let result = if path == Path::new(STANDARD_STREAM) {
+        let mut output = stdout().lock();
+        write(&mut output).and_then(|_| output.flush())
+    } else {
+        File::create(path).and_then(|file| {
+            let mut output = BufWriter::new(file);
+            write(&mut output)?;
+            output.flush()
+        })
+    };
+    result.map_err(io_error(path))
+}
+
+/// Attach the path of the po file to a [`PoReadError`]
+fn po_read_error(path: &Path) -> impl FnOnce(PoReadError) -> PipelineError + '_ {
+    move |err| match err {
+        PoReadError::Io(err) => PipelineError::Io(path.to_path_buf(), err),
+        PoReadError::Parse(errors) => PipelineError::Po(path.to_path_buf(), errors),
+    }
+}
+
+/// Return the group of ``source_file``. The source files come from the po files, so they are checked to be plain
+/// file names, like when the message files are written, and their group to name a po file of the folder.
+fn checked_group_of(grouping: &PoGrouping, source_file: &str) -> Result<String, PipelineError> {
+    let group = grouping.group_of(source_file);
+    if is_plain_file_name(source_file) && is_plain_file_name(&format!("{}.po", group)) {
+        Ok(group)
+    } else {
+        Err(PipelineError::UnsafeSourceFile(source_file.to_string()))
+    }
+}
+
+/// Write the po files of a group in the ``path`` folder, split in parts as set by the grouping.
+/// The header is written at the start of each file. Return the paths of the written files.
+fn write_po_group(
+    path: &Path,
+    grouping: &PoGrouping,
+    group: &str,
+    header: Option<&PoEntry>,
+    entries: &[PoEntry],
+) -> Result<Vec<PathBuf>, PipelineError> {
+    let mut written = Vec::new();
+    for (file_name, range) in grouping.parts(group, entries.len()) {
+        let file_path = path.join(file_name);
+        write_po_file(&file_path, |output| {
+            write_po_to(header.into_iter().chain(&entries[range]), output)
+        })?;
+        written.push(file_path);
+    }
+    Ok(written)
+}
+
+/// Return the po files of the ``path`` folder that weren't just written, like the ones of an earlier grouping,
+/// removing them if ``prune`` is set. They would otherwise be read along with the new files, and override their
+/// translations.
+fn stale_po_files(
+    path: &Path,
+    written: &HashSet<PathBuf>,
+    prune: bool,
+) -> Result<Vec<PathBuf>, PipelineError> {
+    let mut stale = Vec::new();
+    for file_entry in read_dir(path).map_err(io_error(path))? {
+        let file_path = file_entry.map_err(io_error(path))?.path();
+        if file_path.extension() == Some(OsStr::new("po"))
+            && file_path.is_file()
+            && !written.contains(&file_path)
+        {
+            if prune {
+                remove_file(&file_path).map_err(io_error(&file_path))?;
+            };
+            stale.push(file_path);
+        };
+    }
+    stale.sort();
+    Ok(stale)
+}
+
+impl PoStorageMode {
+    /// Write the catalog as po, with its translations. In folder mode, the messages are split in files
+    /// as set by ``grouping``, and the other po files of the folder are returned. They were removed if
+    /// [`PoGrouping::prune`] is set.
+    pub fn write(
+        self,
+        path: &Path,
+        content: Catalog,
+        grouping: &PoGrouping,
+    ) -> Result<Vec<PathBuf>, PipelineError> {
+        match self {
+            PoStorageMode::File => {
+                write_po_file(path, |output| content.write_po_to(output))?;
+                Ok(Vec::new())
+            }
+            PoStorageMode::Folder => {
+                for entry in &content.entries {
+                    checked_group_of(grouping, &entry.source_file)?;
+                }
+                create_dir_all(path).map_err(io_error(path))?;
+                let mut written = HashSet::new();
+                for (group, gettext) in content.split_by_group(grouping).into_iter() {
+                    let mut entries = gettext.to_po_entries();
+                    let header = match entries.first() {
+                        Some(entry) if entry.is_header() => Some(entries.remove(0)),
+                        _ => None,
+                    };
+                    written.extend(write_po_group(
+                        path,
+                        grouping,
+                        &group,
+                        header.as_ref(),
+                        &entries,
+                    )?);
+                }
+                stale_po_files(path, &written, grouping.prune)
+            }
+        }
+    }
+
+    /// Write the po entries, keeping all their metadata. In folder mode, they are split in files as set by
+    /// ``grouping``, using the source file of their first location, and the header is copied in each file. The
+    /// other po files of the folder are returned, as with [`PoStorageMode::write`].
+    pub fn write_po_entries(
+        self,
+        path: &Path,
+        entries: Vec<PoEntry>,
+        grouping: &PoGrouping,
+    ) -> Result<Vec<PathBuf>, PipelineError> {
+        match self {
+            PoStorageMode::File => {
+                write_po_file(path, |output| write_po_to(&entries, output))?;
+                Ok(Vec::new())
+            }
+            PoStorageMode::Folder => {
+                let mut header = None;
+                let mut files: BTreeMap<String, Vec<PoEntry>> = BTreeMap::new();
+                for entry in entries.into_iter() {
+                    if entry.is_header() {
+                        header = Some(entry);
+                        continue;
+                    };
+                    let group = match po_entry_locations(&entry).into_iter().next() {
+                        Some((source_file, _, _)) => checked_group_of(grouping, &source_file)?,
+                        None => String::new(),
+                    };
+                    files.entry(group).or_default().push(entry);
+                }
+                create_dir_all(path).map_err(io_error(path))?;
+                let mut written = HashSet::new();
+                for (group, entries) in files.into_iter() {
+                    written.extend(write_po_group(
+                        path,
+                        grouping,
+                        &group,
+                        header.as_ref(),
+                        &entries,
+                    )?);
+                }
+                stale_po_files(path, &written, grouping.prune)
+            }
+        }
+    }
+
+    /// The paths of the po files to read. The files of a folder are read in the order of their name, so the
+    /// parts of a group are read in order.
+    fn po_files(self, path: &Path) -> Result<Vec<PathBuf>, PipelineError> {
+        match self {
+            Self::File => Ok(vec![path.to_path_buf()]),
+            Self::Folder => {
+                let mut files = read_dir(path)
+                    .map_err(io_error(path))?
+                    .map(|entry| entry.map(|entry| entry.path()).map_err(io_error(path)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                files.sort();
+                Ok(files)
+            }
+        }
+    }
+
+    /// Read the po entries, keeping all their metadata. Also return the non fatal warnings.
+    pub fn read_po_entries(
+        self,
+        path: &Path,
+    ) -> Result<(Vec<PoEntry>, Vec<PoWarning>), PipelineError> {
+        let (files, warnings) = self.read_po_files(path)?;
+        let entries = files.into_iter().flat_map(|(_, entries)| entries).collect();
+        Ok((entries, warnings))
+    }
+
+    /// Read the po entries of each po file, with the path of the file. Also return the non fatal warnings.
+    pub fn read_po_files(self, path: &Path) -> Result<(PoFiles, Vec<PoWarning>), PipelineError> {
+        let mut files = Vec::new();
+        let mut warnings = Vec::new();
+        for file_path in self.po_files(path)? {
+            let mut reader = PoReader::new(open_po_file(&file_path)?);
+            let mut entries = Vec::new();
+            for entry in reader.by_ref() {
+                entries.push(entry.map_err(io_error(&file_path))?);
+            }
+            let errors = reader.take_errors();
+            if !errors.is_empty() {
+                return Err(PipelineError::Po(file_path, PoErrors(errors)));
+            };
+            warnings.append(&mut reader.take_warnings());
+            files.push((file_path, entries));
+        }
+        Ok((files, warnings))
+    }
+
+    /// Read the translated catalog. Also return the non fatal warnings, including one per fuzzy translation.
+    pub fn read(
+        self,
+        path: &Path,
+        fuzzy_policy: FuzzyPolicy,
+    ) -> Result<(Catalog, Vec<PoWarning>), PipelineError> {
+        self.read_with(path, fuzzy_policy, open_po_file)
+    }
+
+    /// Read the translated catalog like [`PoStorageMode::read`], opening the po files with ``open``
+    fn read_with(
+        self,
+        path: &Path,
+        fuzzy_policy: FuzzyPolicy,
+        open: impl Fn(&Path) -> Result<Box<dyn BufRead>, PipelineError>,
+    ) -> Result<(Catalog, Vec<PoWarning>), PipelineError> {
+        let mut catalog = GettextWriter::new(Vec::new());
+        let mut warnings = Vec::new();
+        for file_path in self.po_files(path)? {
+            let (mut translation, mut file_warnings) =
+                GettextWriter::from_po_reader(open(&file_path)?, fuzzy_policy)
+                    .map_err(po_read_error(&file_path))?;
+            for entry in &mut translation.entries {
+                entry.po_file = Some(file_path.clone());
+            }
+            catalog.merge(translation);
+            warnings.append(&mut file_warnings);
+        }
+        Ok((catalog, warnings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Entry, PoHeader};
+    use pmd_message::MessageBin;
+    use std::{env, fs::remove_dir_all};
+
+    /// Return an empty folder in the temporary directory for the ``name`` test
+    fn test_folder(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("pmdtranslate-{}-{}", name, std::process::id()));
+        if path.exists() {
+            remove_dir_all(&path).unwrap();
+        };
+        path
+    }
+
+    /// The entries of a catalog, without what depend on where they were read from, in a stable order. The context
+    /// is also ignored, as it is only set by the discriminator when the entries are written.
+    fn comparable_entries(catalog: &Catalog) -> Vec<ComparableEntry> {
+        let mut entries: Vec<ComparableEntry> = catalog
+            .entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.source_file.clone(),
+                    entry.hash,
+                    entry.unk,
+                    entry.text.clone(),
+                    entry.translation.clone(),
+                )
+            })
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    type ComparableEntry = (String, u32, u32, String, Option<String>);
+
+    fn translated_catalog() -> Catalog {
+        let mut catalog = Catalog::new(vec!["Yes".to_string()]);
+        let mut header = PoHeader::new_template("PSMD".to_string(), "fr".to_string());
+        header.pot_creation_date = Some("2022-03-20 14:05+0000".to_string());
+        catalog.header = Some(header);
+        for (source_file, count) in [("script_a.bin", 4), ("script_b.bin", 3), ("menu.bin", 2)] {
+            let mut message_bin = MessageBin::default();
+            for hash in 0..count {
+                message_bin.insert(hash, hash % 2, format!("{} {}", source_file, hash % 3));
+            }
+            message_bin.insert(100, 0, "Yes".to_string());
+            catalog.push_message_bin(&message_bin, source_file);
+        }
+        for (position, entry) in catalog.entries.iter_mut().enumerate() {
+            if position % 3 != 0 {
+                entry.translation = Some(format!("translation {}", position));
+            };
+        }
+        catalog
+    }
+
+    #[test]
+    fn grouped_folder_is_read_back_identically() {
+        let catalog = translated_catalog();
+        let mut grouping = PoGrouping::default();
+        grouping.add_rule("script_*".to_string(), "script".to_string());
+        grouping.max_entries = Some(2);
+
+        let path = test_folder("grouped");
+        let stale = PoStorageMode::Folder
+            .write(&path, translated_catalog(), &grouping)
+            .unwrap();
+        assert!(stale.is_empty());
+        let mut file_names: Vec<String> = read_dir(&path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        file_names.sort();
+        assert_eq!(
+            file_names,
+            [
+                "menu-1.po",
+                "menu-2.po",
+                "script-1.po",
+                "script-2.po",
+                "script-3.po",
+                "script-4.po"
+            ]
+        );
+
+        let (reread, warnings) = PoStorageMode::Folder.read(&path, FuzzyPolicy::Use).unwrap();
+        remove_dir_all(&path).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(reread.header, catalog.header);
+        assert_eq!(comparable_entries(&reread), comparable_entries(&catalog));
+        let (rebuilt, original) = (reread.to_message_bins(), catalog.to_message_bins());
+        assert_eq!(rebuilt.len(), original.len());
+        for (source_file, message_bin) in original {
+            assert_eq!(rebuilt[&source_file].messages(), message_bin.messages());
+        }
+    }
+
+    #[test]
+    fn other_po_files_are_only_removed_with_prune() {
+        let path = test_folder("prune");
+        create_dir_all(&path).unwrap();
+        let old_file = path.join("old.po");
+        File::create(&old_file).unwrap();
+        let notes = path.join("notes.txt");
+        File::create(&notes).unwrap();
+
+        let mut grouping = PoGrouping::default();
+        let stale = PoStorageMode::Folder
+            .write(&path, translated_catalog(), &grouping)
+            .unwrap();
+        assert_eq!(stale, vec![old_file.clone()]);
+        assert!(old_file.exists());
+
+        grouping.prune = true;
+        let stale = PoStorageMode::Folder
+            .write(&path, translated_catalog(), &grouping)
+            .unwrap();
+        assert_eq!(stale, vec![old_file.clone()]);
+        assert!(!old_file.exists());
+        assert!(notes.exists());
+        assert!(path.join("menu.po").exists());
+        remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn standard_stream_is_read_like_a_file() {
+        let po = translated_catalog().to_po();
+        let path = test_folder("standard-stream");
+        create_dir_all(&path).unwrap();
+        let file_path = path.join("translation.po");
+        std::fs::write(&file_path, &po).unwrap();
+        let (from_file, file_warnings) = PoStorageMode::File
+            .read(&file_path, FuzzyPolicy::Use)
+            .unwrap();
+        remove_dir_all(&path).unwrap();
+
+        let standard_stream = Path::new(STANDARD_STREAM);
+        let (from_stream, stream_warnings) = PoStorageMode::File
+            .read_with(standard_stream, FuzzyPolicy::Use, |path| {
+                assert_eq!(path, standard_stream);
+                Ok(Box::new(BufReader::with_capacity(
+                    3,
+                    io::Cursor::new(po.clone().into_bytes()),
+                )))
+            })
+            .unwrap();
+        assert_eq!(
+            format!("{:?}", stream_warnings),
+            format!("{:?}", file_warnings)
+        );
+        assert_eq!(from_stream.header, from_file.header);
+        assert_eq!(
+            comparable_entries(&from_stream),
+            comparable_entries(&from_file)
+        );
+        for (streamed, read) in from_stream.entries.iter().zip(&from_file.entries) {
+            assert_eq!(streamed.po_line, read.po_line);
+            assert_eq!(streamed.po_file.as_deref(), Some(standard_stream));
+            assert_eq!(read.po_file.as_ref(), Some(&file_path));
+        }
+    }
+
+    #[test]
+    fn source_files_are_written_in_the_folder() {
+        let path = test_folder("unsafe-source-file");
+        let output = path.join("po");
+        let mut grouping = PoGrouping::default();
+        grouping.add_rule("rule.bin".to_string(), "../rule".to_string());
+        for source_file in ["/tmp/x.bin", "../x.bin", "../../x", "sub/x.bin", "rule.bin"] {
+            let mut catalog = Catalog::new(Vec::new());
+            catalog.entries.push(Entry::new(
+                "text".to_string(),
+                1,
+                0,
+                source_file.to_string(),
+            ));
+            assert!(
+                matches!(
+                    PoStorageMode::Folder.write(&output, catalog, &grouping),
+                    Err(PipelineError::UnsafeSourceFile(unsafe_file)) if unsafe_file == source_file
+                ),
+                "{:?} was accepted",
+                source_file
+            );
+
+            let entry = PoEntry {
+                references: vec![format!("{}:0x00000001", source_file)],
+                msgid: "text".to_string(),
+                msgstr: vec![String::new()],
+                ..Default::default()
+            };
+            assert!(
+                matches!(
+                    PoStorageMode::Folder.write_po_entries(&output, vec![entry], &grouping),
+                    Err(PipelineError::UnsafeSourceFile(unsafe_file)) if unsafe_file == source_file
+                ),
+                "{:?} was accepted",
+                source_file
+            );
+        }
+        assert!(!path.exists());
+    }
+}
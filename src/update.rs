@@ -0,0 +1,398 @@
+use crate::{
+    is_location_flag, po_entry_locations, GettextWriter, PoEntry, PoHeader, DISCRIMINATOR,
+};
+use std::collections::HashMap;
+
+/// Minimal similarity (as computed by [`strsim::normalized_levenshtein`]) for a translation to be reused as a fuzzy one
+const SIMILARITY_THRESHOLD: f64 = 0.7;
+
+/// Statistics about what happened to the translations during a [`GettextWriter::update`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UpdateReport {
+    /// Entries that kept their translation unchanged
+    pub kept: usize,
+    /// Entries whose source text changed at the same location. Their old translation is kept, marked fuzzy.
+    pub changed_source: usize,
+    /// Entries that got the translation of a similar string, marked fuzzy.
+    pub similar: usize,
+    /// Entries without translation
+    pub untranslated: usize,
+    /// Old translations that are not used anymore, and kept as obsolete entries
+    pub obsolete: usize,
+}
+
+/// The msgid, without the discriminator suffix of older files
+fn normalized_msgid(entry: &PoEntry) -> &str {
+    entry.msgid.split(DISCRIMINATOR).next().unwrap_or("")
+}
+
+/// The msgctxt and msgid of this entry. Older files store the context as a suffix of the msgid rather than
+/// in a msgctxt, like [`GettextWriter::push_po_entry`] read them.
+fn context_and_msgid(entry: &PoEntry) -> (Option<&str>, &str) {
+    let mut msgid_splited = entry.msgid.splitn(2, DISCRIMINATOR);
+    let msgid = msgid_splited.next().unwrap_or("");
+    match msgid_splited.next() {
+        Some(legacy_context) => (Some(legacy_context.trim()), msgid),
+        None => (entry.msgctxt.as_deref(), msgid),
+    }
+}
+
+/// The ``(source_file, hash)`` pairs this entry is used at
+fn locations(entry: &PoEntry) -> Vec<(String, u32)> {
+    po_entry_locations(entry)
+        .into_iter()
+        .map(|(source_file, hash, _)| (source_file, hash))
+        .collect()
+}
+
+impl GettextWriter {
+    /// Merge the translations of ``existing`` into a newly extracted ``template``, like gettext's ``msgmerge``.
+    ///
+    /// An entry of the template keep the translation of an existing entry that is at the same ``(source_file, hash)``,
+    /// or that has the same text. If the text at a location changed, or if only a similar text could be found
+    /// (when ``fuzzy_matching`` is true), the translation is marked as fuzzy, with the old text as the previous msgid.
+    /// Existing translations that aren't used anymore are kept as obsolete entries.
+    pub fn update(
+        existing: Vec<PoEntry>,
+        template: Vec<PoEntry>,
+        fuzzy_matching: bool,
+    ) -> (Vec<PoEntry>, UpdateReport) {
+        let mut report = UpdateReport::default();
+        let mut result = Vec::new();
+
+        let mut header = None;
+        let mut old_entries = Vec::new();
+        for entry in existing {
+            if entry.is_header() && !entry.obsolete {
+                header = Some(entry);
+            } else if entry.translation().is_some() {
+                old_entries.push(entry);
+            }
+        }
+
+        let mut by_location: HashMap<(String, u32), usize> = HashMap::new();
+        let mut by_msgid: HashMap<(Option<&str>, &str), usize> = HashMap::new();
+        for (index, entry) in old_entries.iter().enumerate() {
+            if !entry.obsolete {
+                for location in locations(entry) {
+                    by_location.entry(location).or_insert(index);
+                }
+            }
+            by_msgid.entry(context_and_msgid(entry)).or_insert(index);
+        }
+        let mut used = vec![false; old_entries.len()];
+
+        for mut entry in template {
+            if entry.is_header() {
+                match &mut header {
+                    None => header = Some(entry),
+                    Some(existing_header) => {
+                        // keep the existing header, but with the creation date of the new template, like msgmerge
+                        let template_date = PoHeader::from_po_entry(&entry)
+                            .and_then(|template_header| template_header.pot_creation_date);
+                        if let (Some(date), Some(mut updated_header)) =
+                            (template_date, PoHeader::from_po_entry(existing_header))
+                        {
+                            updated_header.pot_creation_date = Some(date);
+                            existing_header.msgstr = vec![updated_header.to_content()];
+                        }
+                    }
+                };
+                continue;
+            }
+
+            let new_locations = locations(&entry);
+            let same_location = new_locations
+                .iter()
+                .find_map(|location| by_location.get(location).copied());
+
+            let (matched, fuzzy) = match same_location {
+                Some(index) if normalized_msgid(&old_entries[index]) == entry.msgid => {
+                    (Some(index), false)
+                }
+                _ => {
+                    if let Some(index) = by_msgid.get(&context_and_msgid(&entry)).copied() {
+                        (Some(index), false)
+                    } else if let Some(index) = same_location {
+                        report.changed_source += 1;
+                        (Some(index), true)
+                    } else if fuzzy_matching {
+                        let similar = find_similar(&old_entries, &entry.msgid);
+                        if similar.is_some() {
+                            report.similar += 1;
+                        };
+                        (similar, true)
+                    } else {
+                        (None, false)
+                    }
+                }
+            };
+
+            if let Some(index) = matched {
+                used[index] = true;
+                let old = &old_entries[index];
+                entry.msgstr = old.msgstr.clone();
+                entry.translator_comments = old.translator_comments.clone();
+                // the values of the locations come from the new template, the other flags from the translation
+                let location_flags = entry.flags.drain(..).filter(|flag| is_location_flag(flag));
+                entry.flags = old
+                    .flags
+                    .iter()
+                    .filter(|flag| !is_location_flag(flag))
+                    .cloned()
+                    .chain(location_flags)
+                    .collect();
+                if fuzzy {
+                    if !entry.has_flag("fuzzy") {
+                        entry.flags.push("fuzzy".to_string());
+                    };
+                    entry.previous_msgctxt = old.msgctxt.clone();
+                    entry.previous_msgid = Some(normalized_msgid(old).to_string());
+                } else {
+                    report.kept += 1;
+                }
+            } else {
+                report.untranslated += 1;
+            }
+
+            result.push(entry);
+        }
+
+        for (entry, used) in old_entries.into_iter().zip(used) {
+            if !used {
+                report.obsolete += 1;
+                result.push(PoEntry {
+                    obsolete: true,
+                    ..entry
+                });
+            }
+        }
+
+        if let Some(header) = header {
+            result.insert(0, header);
+        }
+
+        (result, report)
+    }
+}
+
+/// Return the index of the old entry with the text most similar to ``msgid``, if it is similar enough
+fn find_similar(old_entries: &[PoEntry], msgid: &str) -> Option<usize> {
+    let msgid_len = msgid.chars().count() as f64;
+    let mut best = None;
+    let mut best_similarity = SIMILARITY_THRESHOLD;
+    for (index, old) in old_entries.iter().enumerate() {
+        let old_msgid = normalized_msgid(old);
+        // strings of too different length can't be similar enough, so avoid the costly comparison
+        let old_len = old_msgid.chars().count() as f64;
+        if (old_len - msgid_len).abs() > (1.0 - SIMILARITY_THRESHOLD) * old_len.max(msgid_len) {
+            continue;
+        }
+        let similarity = strsim::normalized_levenshtein(old_msgid, msgid);
+        if similarity >= best_similarity {
+            best_similarity = similarity;
+            best = Some(index);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(references: &[&str], msgid: &str, msgstr: &str) -> PoEntry {
+        PoEntry {
+            references: references.iter().map(|x| x.to_string()).collect(),
+            msgid: msgid.to_string(),
+            msgstr: vec![msgstr.to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn header(creation_date: &str, language: &str) -> PoEntry {
+        PoHeader {
+            pot_creation_date: Some(creation_date.to_string()),
+            language: Some(language.to_string()),
+            ..Default::default()
+        }
+        .to_po_entry()
+    }
+
+    #[test]
+    fn same_location_and_text_keep_the_translation() {
+        let mut old = entry(&["a.bin:0x00000001"], "Yes", "Oui");
+        old.translator_comments = vec!["checked".to_string()];
+        old.flags = vec![
+            "no-wrap".to_string(),
+            "pmd-unk:a.bin:0x00000001=1".to_string(),
+        ];
+        let mut new = entry(&["a.bin:0x00000001"], "Yes", "");
+        new.flags = vec!["pmd-unk:a.bin:0x00000001=2".to_string()];
+
+        let (result, report) = GettextWriter::update(vec![old], vec![new], true);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].translation(), Some("Oui"));
+        assert_eq!(result[0].translator_comments, vec!["checked"]);
+        // the location values come from the template, the other flags from the translation
+        assert_eq!(
+            result[0].flags,
+            vec!["no-wrap", "pmd-unk:a.bin:0x00000001=2"]
+        );
+        assert_eq!(
+            report,
+            UpdateReport {
+                kept: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn moved_text_keep_the_translation() {
+        let old = entry(&["a.bin:0x00000001"], "Yes", "Oui");
+        let new = entry(&["b.bin:0x00000002"], "Yes", "");
+
+        let (result, report) = GettextWriter::update(vec![old], vec![new], false);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].translation(), Some("Oui"));
+        assert_eq!(result[0].references, vec!["b.bin:0x00000002"]);
+        assert!(!result[0].has_flag("fuzzy"));
+        assert_eq!(report.kept, 1);
+    }
+
+    #[test]
+    fn changed_source_is_fuzzy() {
+        let old = entry(&["a.bin:0x00000001"], "Go north", "Va au nord");
+        let new = entry(&["a.bin:0x00000001"], "Head to the east", "");
+
+        let (result, report) = GettextWriter::update(vec![old], vec![new], false);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].translation(), Some("Va au nord"));
+        assert!(result[0].has_flag("fuzzy"));
+        assert_eq!(result[0].previous_msgid.as_deref(), Some("Go north"));
+        assert_eq!(
+            report,
+            UpdateReport {
+                changed_source: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn similar_text_is_fuzzy() {
+        let old = entry(
+            &["a.bin:0x00000001"],
+            "Welcome to the guild!",
+            "Bienvenue à la guilde !",
+        );
+        let new = entry(&["b.bin:0x00000002"], "Welcome to the guild.", "");
+
+        let (result, report) = GettextWriter::update(vec![old.clone()], vec![new.clone()], true);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].translation(), Some("Bienvenue à la guilde !"));
+        assert!(result[0].has_flag("fuzzy"));
+        assert_eq!(
+            result[0].previous_msgid.as_deref(),
+            Some("Welcome to the guild!")
+        );
+        assert_eq!(report.similar, 1);
+
+        // without fuzzy matching, the old translation is obsolete
+        let (result, report) = GettextWriter::update(vec![old], vec![new], false);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].translation(), None);
+        assert!(result[1].obsolete);
+        assert_eq!(
+            report,
+            UpdateReport {
+                untranslated: 1,
+                obsolete: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unused_translations_are_obsolete() {
+        let kept = entry(&["a.bin:0x00000001"], "Yes", "Oui");
+        let removed = entry(
+            &["a.bin:0x00000002"],
+            "Something else entirely",
+            "Autre chose",
+        );
+        let untranslated = entry(&["a.bin:0x00000003"], "Not translated", "");
+        let new = entry(&["a.bin:0x00000001"], "Yes", "");
+
+        let (result, report) =
+            GettextWriter::update(vec![kept, removed, untranslated], vec![new], true);
+        assert_eq!(result.len(), 2);
+        assert!(!result[0].obsolete);
+        assert!(result[1].obsolete);
+        assert_eq!(result[1].msgid, "Something else entirely");
+        assert_eq!(result[1].translation(), Some("Autre chose"));
+        assert_eq!(report.obsolete, 1);
+    }
+
+    #[test]
+    fn legacy_discriminator_match_the_context() {
+        let mut old = entry(&[], "Yesŧdiscrimatorŧ a.bin 1", "Oui");
+        old.extracted_comments = vec!["a.bin 1 0".to_string()];
+        let mut new = entry(&["a.bin:0x00000001"], "Yes", "");
+        new.msgctxt = Some("a.bin 1".to_string());
+
+        let (result, report) = GettextWriter::update(vec![old], vec![new], false);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].translation(), Some("Oui"));
+        assert_eq!(result[0].msgid, "Yes");
+        assert!(!result[0].has_flag("fuzzy"));
+        assert_eq!(report.kept, 1);
+    }
+
+    #[test]
+    fn legacy_discriminator_only_match_its_own_context() {
+        let mut old = entry(&[], "Yesŧdiscrimatorŧ a.bin 1", "Oui");
+        old.extracted_comments = vec!["a.bin 1 0".to_string()];
+        // the string moved to another location, and isn't discriminated anymore
+        let new = entry(&["a.bin:0x00000002"], "Yes", "");
+
+        let (result, report) = GettextWriter::update(vec![old], vec![new], false);
+        assert_eq!(result[0].translation(), None);
+        assert_eq!(report.kept, 0);
+        assert_eq!(report.untranslated, 1);
+        assert!(result[1].obsolete);
+
+        // it can still be reused as a similar string, to be reviewed
+        let mut old = entry(&[], "Yesŧdiscrimatorŧ a.bin 1", "Oui");
+        old.extracted_comments = vec!["a.bin 1 0".to_string()];
+        let mut new = entry(&["a.bin:0x00000002"], "Yes", "");
+        new.msgctxt = Some("a.bin 2".to_string());
+
+        let (result, report) = GettextWriter::update(vec![old], vec![new], true);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].translation(), Some("Oui"));
+        assert!(result[0].has_flag("fuzzy"));
+        assert_eq!(result[0].previous_msgid.as_deref(), Some("Yes"));
+        assert_eq!(report.kept, 0);
+        assert_eq!(report.similar, 1);
+    }
+
+    #[test]
+    fn existing_header_is_kept_with_the_new_creation_date() {
+        let existing = vec![header("2022-01-01 00:00+0000", "fr")];
+        let template = vec![header("2022-02-02 00:00+0000", "")];
+        let (result, _) = GettextWriter::update(existing, template.clone(), true);
+        assert_eq!(result.len(), 1);
+        let updated = PoHeader::from_po_entry(&result[0]).unwrap();
+        assert_eq!(updated.language.as_deref(), Some("fr"));
+        assert_eq!(
+            updated.pot_creation_date.as_deref(),
+            Some("2022-02-02 00:00+0000")
+        );
+
+        // without an existing header, the one of the template is used
+        let (result, _) = GettextWriter::update(Vec::new(), template.clone(), true);
+        assert_eq!(result, template);
+    }
+}